@@ -1,7 +1,6 @@
 //! Monitor an SHTC3 sensor on Linux in the terminal.
 
 use std::{
-    collections::VecDeque,
     io::{self, Stdout},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -13,7 +12,7 @@ use std::{
 };
 
 use linux_embedded_hal::{Delay, I2cdev};
-use shtcx::{self, PowerMode};
+use shtcx::{self, history::History, Measurement, PowerMode};
 use termion::{
     event::Key,
     input::TermRead,
@@ -49,7 +48,7 @@ fn main() -> Result<(), io::Error> {
     });
 
     // Launch measurement thread
-    let mut data = Data::new(DATA_CAPACITY);
+    let mut data = Data::default();
     let (sender, receiver) = channel();
     thread::spawn(move || {
         // Initialize sensor driver
@@ -83,17 +82,10 @@ fn main() -> Result<(), io::Error> {
     while run_render_loop.load(Ordering::SeqCst) {
         // Drain any data updating the buffer
         for (normal, lowpwr) in receiver.try_iter() {
-            data.temp_normal
-                .push_front(normal.temperature.as_millidegrees_celsius());
-            data.temp_lowpwr
-                .push_front(lowpwr.temperature.as_millidegrees_celsius());
-            data.humi_normal
-                .push_front(normal.humidity.as_millipercent());
-            data.humi_lowpwr
-                .push_front(lowpwr.humidity.as_millipercent());
+            data.normal.push(normal);
+            data.lowpwr.push(lowpwr);
         }
 
-        data.truncate();
         render(&mut terminal, &data);
 
         thread::sleep(UI_REFRESH_DELAY);
@@ -108,30 +100,24 @@ fn main() -> Result<(), io::Error> {
 
 #[derive(Default)]
 struct Data {
-    capacity: usize,
-    temp_normal: VecDeque<i32>,
-    temp_lowpwr: VecDeque<i32>,
-    humi_normal: VecDeque<i32>,
-    humi_lowpwr: VecDeque<i32>,
+    normal: History<DATA_CAPACITY>,
+    lowpwr: History<DATA_CAPACITY>,
 }
 
-impl Data {
-    fn new(capacity: usize) -> Self {
-        Self {
-            capacity,
-            ..Default::default()
-        }
-    }
-
-    /// Truncate data to max `capacity` datapoints.
-    fn truncate(&mut self) {
-        self.temp_normal.truncate(self.capacity);
-        self.temp_lowpwr.truncate(self.capacity);
-        self.humi_normal.truncate(self.capacity);
-        self.humi_lowpwr.truncate(self.capacity);
-    }
+/// Return the chart points (oldest first) of a history.
+fn points(
+    history: &History<DATA_CAPACITY>,
+    value: impl Fn(&Measurement) -> i32,
+) -> Vec<(f64, f64)> {
+    history
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, entry)| (i as f64, value(&entry.measurement) as f64 / 1000.0))
+        .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 fn show_chart<B: Backend>(
     title: &str,
     max: (f64, &str),
@@ -181,34 +167,12 @@ fn render(terminal: &mut Terminal<CrosstermBackend<RawTerminal<Stdout>>>, data:
                 .margin(1)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
                 .split(f.size());
-            let (temp_normal, temp_lowpwr, humi_normal, humi_lowpwr) = {
-                (
-                    data.temp_normal
-                        .iter()
-                        .rev()
-                        .enumerate()
-                        .map(|(i, x): (usize, &i32)| (i as f64, (*x as f64) / 1000.0))
-                        .collect::<Vec<_>>(),
-                    data.temp_lowpwr
-                        .iter()
-                        .rev()
-                        .enumerate()
-                        .map(|(i, x): (usize, &i32)| (i as f64, (*x as f64) / 1000.0))
-                        .collect::<Vec<_>>(),
-                    data.humi_normal
-                        .iter()
-                        .rev()
-                        .enumerate()
-                        .map(|(i, x): (usize, &i32)| (i as f64, (*x as f64) / 1000.0))
-                        .collect::<Vec<_>>(),
-                    data.humi_lowpwr
-                        .iter()
-                        .rev()
-                        .enumerate()
-                        .map(|(i, x): (usize, &i32)| (i as f64, (*x as f64) / 1000.0))
-                        .collect::<Vec<_>>(),
-                )
-            };
+            let temperature = |m: &Measurement| m.temperature.as_millidegrees_celsius();
+            let humidity = |m: &Measurement| m.humidity.as_millipercent();
+            let temp_normal = points(&data.normal, temperature);
+            let temp_lowpwr = points(&data.lowpwr, temperature);
+            let humi_normal = points(&data.normal, humidity);
+            let humi_lowpwr = points(&data.lowpwr, humidity);
             show_chart(
                 "Temperature",
                 (50.0, "50"),
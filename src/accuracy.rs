@@ -0,0 +1,346 @@
+//! Accuracy metadata of measurements.
+//!
+//! The accuracy of the sensors depends on the measured values. The
+//! [`SensorClass::accuracy`] function returns the typical and maximum
+//! tolerance at a given temperature and humidity, based on the accuracy
+//! figures of the datasheets. This allows showing measurements like
+//! `23.7 ± 0.2 °C`.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::PowerMode;
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut delay = Delay;
+//! let annotated = sht.measure_with_metadata(PowerMode::NormalMode, &mut delay).unwrap();
+//! println!(
+//!     "{:.1} ± {:.1} °C",
+//!     annotated.measurement.temperature.as_degrees_celsius(),
+//!     annotated.metadata.accuracy.temperature.typical as f32 / 1000.0,
+//! );
+//! ```
+//!
+//! Note: The tolerances are approximated with piecewise-linear curves from
+//! the datasheet figures. The humidity tolerances are specified at 25 °C.
+//! They are specified for normal mode: In low power mode, the repeatability
+//! of the temperature is lower (see [`PowerMode`]).
+
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{I2c, SevenBitAddress},
+};
+
+use crate::{sensor_class, Error, Measurement, MeasurementDuration, PowerMode, ShtCx, ShtSensor};
+
+/// A sensor class, see [`sensor_class`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SensorClass {
+    /// First generation sensors (SHTC1, SHTW2).
+    Sht1Gen,
+    /// Second generation sensors (SHTC3).
+    Sht2Gen,
+    /// Any sensor (the generic driver).
+    ShtGeneric,
+}
+
+/// Sensor class of a sensor type parameter.
+pub trait SensorAccuracy: ShtSensor {
+    /// The sensor class.
+    const SENSOR_CLASS: SensorClass;
+}
+
+impl SensorAccuracy for sensor_class::Sht1Gen {
+    const SENSOR_CLASS: SensorClass = SensorClass::Sht1Gen;
+}
+
+impl SensorAccuracy for sensor_class::Sht2Gen {
+    const SENSOR_CLASS: SensorClass = SensorClass::Sht2Gen;
+}
+
+impl SensorAccuracy for sensor_class::ShtGeneric {
+    const SENSOR_CLASS: SensorClass = SensorClass::ShtGeneric;
+}
+
+/// Tolerance of a value in milli-units (m°C or 1/1000 %RH).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Typical tolerance (±).
+    pub typical: u32,
+    /// Maximum tolerance (±).
+    pub maximum: u32,
+}
+
+/// Tolerances of a measurement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Accuracy {
+    /// Tolerance of the temperature in m°C.
+    pub temperature: Tolerance,
+    /// Tolerance of the humidity in 1/1000 %RH.
+    pub humidity: Tolerance,
+}
+
+/// Tolerance curve: Points of (value, tolerance) sorted by value. The
+/// tolerance is interpolated linearly between the points and constant
+/// outside of them.
+type Curve = &'static [(i32, u32)];
+
+/// Tolerance curves of a sensor class.
+struct Curves {
+    temperature_typical: Curve,
+    temperature_maximum: Curve,
+    humidity_typical: Curve,
+    humidity_maximum: Curve,
+}
+
+/// SHTC1 / SHTW2 datasheets: ±0.3 °C typ (5..60 °C), ±3 %RH typ
+/// (20..80 %RH).
+const SHT1GEN_CURVES: Curves = Curves {
+    temperature_typical: &[(-40_000, 700), (5_000, 300), (60_000, 300), (125_000, 900)],
+    temperature_maximum: &[
+        (-40_000, 1_200),
+        (5_000, 400),
+        (60_000, 400),
+        (125_000, 1_400),
+    ],
+    humidity_typical: &[
+        (0, 4_500),
+        (20_000, 3_000),
+        (80_000, 3_000),
+        (100_000, 4_500),
+    ],
+    humidity_maximum: &[
+        (0, 7_000),
+        (20_000, 4_500),
+        (80_000, 4_500),
+        (100_000, 7_000),
+    ],
+};
+
+/// SHTC3 datasheet: ±0.2 °C typ (0..60 °C), ±2 %RH typ (20..80 %RH).
+const SHT2GEN_CURVES: Curves = Curves {
+    temperature_typical: &[(-40_000, 600), (0, 200), (60_000, 200), (125_000, 700)],
+    temperature_maximum: &[(-40_000, 1_000), (0, 300), (60_000, 300), (125_000, 1_100)],
+    humidity_typical: &[
+        (0, 3_000),
+        (20_000, 2_000),
+        (80_000, 2_000),
+        (100_000, 3_000),
+    ],
+    humidity_maximum: &[
+        (0, 5_000),
+        (20_000, 3_500),
+        (80_000, 3_500),
+        (100_000, 5_000),
+    ],
+};
+
+/// Interpolate a tolerance curve at `value`.
+fn interpolate(curve: Curve, value: i32) -> u32 {
+    let (first, last) = (curve[0], curve[curve.len() - 1]);
+    if value <= first.0 {
+        return first.1;
+    }
+    if value >= last.0 {
+        return last.1;
+    }
+    let i = curve.partition_point(|point| point.0 <= value);
+    let ((x0, y0), (x1, y1)) = (curve[i - 1], curve[i]);
+    let (x0, y0, x1, y1) = (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+    (y0 + (i64::from(value) - x0) * (y1 - y0) / (x1 - x0)) as u32
+}
+
+impl SensorClass {
+    fn curves(self) -> &'static Curves {
+        match self {
+            SensorClass::Sht1Gen => &SHT1GEN_CURVES,
+            SensorClass::Sht2Gen => &SHT2GEN_CURVES,
+            // Because these tolerances should cover all sensor models, the
+            // least accurate model is chosen.
+            SensorClass::ShtGeneric => &SHT1GEN_CURVES,
+        }
+    }
+
+    /// Return the datasheet tolerances at the temperature and humidity of a
+    /// measurement.
+    pub fn accuracy(self, measurement: &Measurement) -> Accuracy {
+        let curves = self.curves();
+        let temperature = measurement.temperature.as_millidegrees_celsius();
+        let humidity = measurement.humidity.as_millipercent();
+        Accuracy {
+            temperature: Tolerance {
+                typical: interpolate(curves.temperature_typical, temperature),
+                maximum: interpolate(curves.temperature_maximum, temperature),
+            },
+            humidity: Tolerance {
+                typical: interpolate(curves.humidity_typical, humidity),
+                maximum: interpolate(curves.humidity_maximum, humidity),
+            },
+        }
+    }
+}
+
+/// Metadata of a measurement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeasurementMetadata {
+    /// The power mode used for the measurement.
+    pub power_mode: PowerMode,
+    /// The sensor class.
+    pub sensor_class: SensorClass,
+    /// The datasheet tolerances at the measured values.
+    pub accuracy: Accuracy,
+}
+
+impl MeasurementMetadata {
+    /// Create the metadata of a measurement.
+    pub fn new(
+        sensor_class: SensorClass,
+        power_mode: PowerMode,
+        measurement: &Measurement,
+    ) -> Self {
+        Self {
+            power_mode,
+            sensor_class,
+            accuracy: sensor_class.accuracy(measurement),
+        }
+    }
+}
+
+/// A measurement with metadata.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AnnotatedMeasurement {
+    /// The measurement.
+    pub measurement: Measurement,
+    /// The metadata.
+    pub metadata: MeasurementMetadata,
+}
+
+/// Measurements with metadata.
+impl<S, I2C> ShtCx<S, I2C>
+where
+    S: SensorAccuracy + MeasurementDuration,
+    I2C: I2c<SevenBitAddress>,
+{
+    /// Run a temperature/humidity measurement and return it together with
+    /// its metadata.
+    ///
+    /// This is a blocking function call, see [`ShtCx::measure`].
+    pub fn measure_with_metadata(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<AnnotatedMeasurement, Error<I2C::Error>> {
+        let measurement = self.measure(mode, delay)?;
+        Ok(AnnotatedMeasurement {
+            measurement,
+            metadata: MeasurementMetadata::new(S::SENSOR_CLASS, mode, &measurement),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc1, Humidity, Temperature};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let curve: Curve = &[(0, 100), (10, 200), (20, 200)];
+        assert_eq!(interpolate(curve, -5), 100);
+        assert_eq!(interpolate(curve, 0), 100);
+        assert_eq!(interpolate(curve, 5), 150);
+        assert_eq!(interpolate(curve, 10), 200);
+        assert_eq!(interpolate(curve, 15), 200);
+        assert_eq!(interpolate(curve, 25), 200);
+    }
+
+    #[test]
+    fn shtc3() {
+        let accuracy = SensorClass::Sht2Gen.accuracy(&m(25_000, 50_000));
+        assert_eq!(
+            accuracy,
+            Accuracy {
+                temperature: Tolerance {
+                    typical: 200,
+                    maximum: 300,
+                },
+                humidity: Tolerance {
+                    typical: 2_000,
+                    maximum: 3_500,
+                },
+            }
+        );
+        let accuracy = SensorClass::Sht2Gen.accuracy(&m(-20_000, 90_000));
+        assert_eq!(accuracy.temperature.typical, 400);
+        assert_eq!(accuracy.humidity.typical, 2_500);
+        // Out of range values are clamped
+        let accuracy = SensorClass::Sht2Gen.accuracy(&m(130_000, -1_000));
+        assert_eq!(accuracy.temperature.maximum, 1_100);
+        assert_eq!(accuracy.humidity.maximum, 5_000);
+    }
+
+    /// The generic driver uses the tolerances of the least accurate sensor.
+    #[test]
+    fn generic() {
+        for measurement in [
+            m(-40_000, 0),
+            m(0, 10_000),
+            m(25_000, 50_000),
+            m(100_000, 95_000),
+        ] {
+            let generic = SensorClass::ShtGeneric.accuracy(&measurement);
+            for class in [SensorClass::Sht1Gen, SensorClass::Sht2Gen] {
+                let accuracy = class.accuracy(&measurement);
+                assert!(generic.temperature.typical >= accuracy.temperature.typical);
+                assert!(generic.temperature.maximum >= accuracy.temperature.maximum);
+                assert!(generic.humidity.typical >= accuracy.humidity.typical);
+                assert!(generic.humidity.maximum >= accuracy.humidity.maximum);
+            }
+        }
+    }
+
+    #[test]
+    fn measure_with_metadata() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+        ];
+        let mut sht = shtc1(I2cMock::new(&expectations));
+        let annotated = sht
+            .measure_with_metadata(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(annotated.measurement, m(23_730, 62_968));
+        assert_eq!(
+            annotated.metadata,
+            MeasurementMetadata {
+                power_mode: PowerMode::LowPower,
+                sensor_class: SensorClass::Sht1Gen,
+                accuracy: Accuracy {
+                    temperature: Tolerance {
+                        typical: 300,
+                        maximum: 400,
+                    },
+                    humidity: Tolerance {
+                        typical: 3_000,
+                        maximum: 4_500,
+                    },
+                },
+            }
+        );
+        sht.destroy().done();
+    }
+}
@@ -0,0 +1,241 @@
+//! Adaptive selection of the measurement power mode.
+//!
+//! Low power measurements are good enough while conditions are stable. The
+//! [`AdaptiveMode`] helper uses [`PowerMode::LowPower`] by default and
+//! switches to [`PowerMode::NormalMode`] while the temperature changes quickly
+//! or is noisy, based on the last `N` temperature readings.
+//!
+//! The change is measured per reading, not per unit of time, so the
+//! thresholds depend on the sampling period: With a slow sampling rate, a
+//! fast change of the temperature is spread over few readings and may go
+//! unnoticed. For a rate of change per unit of time, use a
+//! [`History`] with timestamps.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::adaptive::AdaptiveMode;
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut delay = Delay;
+//! // Use the last 8 readings, switch to normal mode if the temperature
+//! // changes by more than 0.1 °C per reading or the standard deviation
+//! // exceeds 0.2 °C.
+//! let mut adaptive = AdaptiveMode::<8>::new(100, 200);
+//! let reading = adaptive.measure(&mut sht, &mut delay).unwrap();
+//! println!("{:?}: {} °C", reading.mode, reading.measurement.temperature.as_degrees_celsius());
+//! ```
+
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{I2c, SevenBitAddress},
+};
+
+use crate::{
+    history::History, Error, Measurement, MeasurementDuration, PowerMode, ShtCx, ShtSensor,
+};
+
+/// A measurement together with the power mode that was used.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AdaptiveReading {
+    /// The measurement.
+    pub measurement: Measurement,
+    /// The power mode used for the measurement.
+    pub mode: PowerMode,
+}
+
+/// Adaptive power mode selection based on the last `N` temperature readings.
+#[derive(Debug, Clone)]
+pub struct AdaptiveMode<const N: usize> {
+    /// The last readings.
+    history: History<N>,
+    /// Maximum average temperature change per reading in m°C.
+    max_change: u32,
+    /// Maximum standard deviation of the temperature in m°C.
+    max_std_dev: u32,
+}
+
+impl<const N: usize> AdaptiveMode<N> {
+    /// Create a new helper.
+    ///
+    /// Normal mode is used when the average temperature change per reading
+    /// exceeds `max_change_mdeg` or the standard deviation of the temperature
+    /// exceeds `max_std_dev_mdeg` (both in milli-degrees celsius).
+    pub fn new(max_change_mdeg: u32, max_std_dev_mdeg: u32) -> Self {
+        Self {
+            history: History::new(),
+            max_change: max_change_mdeg,
+            max_std_dev: max_std_dev_mdeg,
+        }
+    }
+
+    /// Forget all previous readings.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Add a measurement (e.g. from a non-blocking measurement).
+    pub fn update(&mut self, measurement: &Measurement) {
+        self.history.push(*measurement);
+    }
+
+    /// Iterate over the stored temperatures, from newest to oldest.
+    fn temperatures(&self) -> impl DoubleEndedIterator<Item = i32> + '_ {
+        self.history
+            .iter()
+            .map(|entry| entry.measurement.temperature.as_millidegrees_celsius())
+    }
+
+    /// Return the average temperature change per reading in m°C (absolute
+    /// value).
+    ///
+    /// This is the change between the oldest and the newest reading, divided
+    /// by the number of readings in between. It doesn't depend on the time
+    /// between the readings.
+    pub fn change_per_reading(&self) -> u32 {
+        let len = self.history.len();
+        if len < 2 {
+            return 0;
+        }
+        let newest = self.temperatures().next().unwrap_or(0);
+        let oldest = self.temperatures().next_back().unwrap_or(0);
+        newest.abs_diff(oldest) / (len as u32 - 1)
+    }
+
+    /// Return the variance of the temperature in (m°C)².
+    pub fn variance(&self) -> u64 {
+        let len = self.history.len();
+        if len < 2 {
+            return 0;
+        }
+        let n = len as i64;
+        let sum: i64 = self.temperatures().map(i64::from).sum();
+        let sum_sq: i64 = self
+            .temperatures()
+            .map(|t| i64::from(t) * i64::from(t))
+            .sum();
+        ((sum_sq * n - sum * sum) / (n * n)) as u64
+    }
+
+    /// Return the power mode that should be used for the next measurement.
+    pub fn next_mode(&self) -> PowerMode {
+        let max_variance = u64::from(self.max_std_dev) * u64::from(self.max_std_dev);
+        if self.change_per_reading() > self.max_change || self.variance() > max_variance {
+            PowerMode::NormalMode
+        } else {
+            PowerMode::LowPower
+        }
+    }
+
+    /// Run a blocking measurement in the power mode chosen by
+    /// [`next_mode`](AdaptiveMode::next_mode) and return it together with the
+    /// mode used.
+    pub fn measure<S, I2C>(
+        &mut self,
+        sht: &mut ShtCx<S, I2C>,
+        delay: &mut impl DelayNs,
+    ) -> Result<AdaptiveReading, Error<I2C::Error>>
+    where
+        S: ShtSensor + MeasurementDuration,
+        I2C: I2c<SevenBitAddress>,
+    {
+        let mode = self.next_mode();
+        let measurement = sht.measure(mode, delay)?;
+        self.update(&measurement);
+        Ok(AdaptiveReading { measurement, mode })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, RawMeasurement};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    /// Create a measurement from a raw temperature value.
+    fn measurement(raw_temperature: u16) -> Measurement {
+        RawMeasurement {
+            temperature: raw_temperature,
+            humidity: 0,
+        }
+        .into()
+    }
+
+    #[test]
+    fn stable_uses_low_power() {
+        let mut adaptive = AdaptiveMode::<4>::new(100, 100);
+        assert_eq!(adaptive.next_mode(), PowerMode::LowPower);
+        for _ in 0..10 {
+            adaptive.update(&measurement(0x6400));
+            assert_eq!(adaptive.next_mode(), PowerMode::LowPower);
+        }
+        assert_eq!(adaptive.change_per_reading(), 0);
+        assert_eq!(adaptive.variance(), 0);
+    }
+
+    #[test]
+    fn change_per_reading() {
+        // ~0.267 °C per raw step of 100
+        let mut adaptive = AdaptiveMode::<4>::new(200, 10_000);
+        adaptive.update(&measurement(0x6400));
+        adaptive.update(&measurement(0x6400 + 100));
+        assert_eq!(adaptive.change_per_reading(), 267);
+        assert_eq!(adaptive.next_mode(), PowerMode::NormalMode);
+
+        // The change falls out of the window
+        for _ in 0..4 {
+            adaptive.update(&measurement(0x6400 + 100));
+        }
+        assert_eq!(adaptive.change_per_reading(), 0);
+        assert_eq!(adaptive.next_mode(), PowerMode::LowPower);
+    }
+
+    #[test]
+    fn variance() {
+        let mut adaptive = AdaptiveMode::<4>::new(10_000, 100);
+        for raw in [0x6400, 0x6400 + 100, 0x6400, 0x6400 + 100] {
+            adaptive.update(&measurement(raw));
+        }
+        // Standard deviation ~133 m°C, but no net change
+        assert_eq!(adaptive.variance(), 17_822);
+        assert_eq!(adaptive.next_mode(), PowerMode::NormalMode);
+
+        adaptive.clear();
+        assert_eq!(adaptive.variance(), 0);
+        assert_eq!(adaptive.next_mode(), PowerMode::LowPower);
+    }
+
+    #[test]
+    fn measure() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x6a, 0x00, 0x3a, 0xa1, 0x33, 0x1c]),
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, vec![0x6a, 0x00, 0x3a, 0xa1, 0x33, 0x1c]),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let mut adaptive = AdaptiveMode::<4>::new(100, 10_000);
+
+        let reading = adaptive.measure(&mut sht, &mut NoopDelay).unwrap();
+        assert_eq!(reading.mode, PowerMode::LowPower);
+        assert_eq!(
+            reading.measurement.temperature.as_millidegrees_celsius(),
+            23_730
+        );
+        let reading = adaptive.measure(&mut sht, &mut NoopDelay).unwrap();
+        assert_eq!(reading.mode, PowerMode::LowPower);
+        // Temperature jumped by more than 3 °C
+        let reading = adaptive.measure(&mut sht, &mut NoopDelay).unwrap();
+        assert_eq!(reading.mode, PowerMode::NormalMode);
+
+        sht.destroy().done();
+    }
+}
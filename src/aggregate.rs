@@ -0,0 +1,282 @@
+//! Aggregation of measurements into time buckets.
+//!
+//! Storing every measurement isn't possible on devices with little storage.
+//! The [`Aggregator`] groups timestamped measurements into fixed time buckets
+//! (e.g. 1 minute, 15 minutes or 1 hour) and emits the number of
+//! measurements, the minimum, the maximum and the mean of every bucket.
+//!
+//! Buckets are aligned to multiples of the bucket duration (based on the
+//! timestamps passed in). A bucket is emitted as soon as a measurement for a
+//! later bucket arrives, or when the aggregator is [flushed](Aggregator::flush).
+//! Buckets without measurements are skipped.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::{aggregate::Aggregator, PowerMode};
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! # fn now_ms() -> u64 { 0 }
+//! let mut delay = Delay;
+//! let mut aggregator = Aggregator::new(Aggregator::FIFTEEN_MINUTES_MS);
+//! loop {
+//!     let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
+//!     if let Some(bucket) = aggregator.add(measurement, now_ms()) {
+//!         println!("{:?}", bucket);
+//!     }
+//! }
+//! ```
+//!
+//! Instead of handling the returned buckets yourself, you can also pass them
+//! to a [`BucketSink`] (e.g. a logger writing to flash) with
+//! [`Aggregator::add_to`].
+
+use crate::{Humidity, Measurement, Temperature};
+
+/// Aggregated measurements of a time bucket.
+///
+/// The minimum and maximum are determined separately for temperature and
+/// humidity, so `min` and `max` usually don't correspond to a single
+/// measurement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    /// Start time of the bucket in milliseconds.
+    pub start_ms: u64,
+    /// Duration of the bucket in milliseconds.
+    pub duration_ms: u32,
+    /// Number of measurements in the bucket.
+    pub count: u32,
+    /// The minimum temperature and humidity.
+    pub min: Measurement,
+    /// The maximum temperature and humidity.
+    pub max: Measurement,
+    /// The mean temperature and humidity.
+    pub mean: Measurement,
+}
+
+/// A consumer of aggregated buckets, e.g. a logger.
+pub trait BucketSink {
+    /// Error type of the sink.
+    type Error;
+
+    /// Write a completed bucket.
+    fn write(&mut self, bucket: &Bucket) -> Result<(), Self::Error>;
+}
+
+/// Running statistics of the current bucket.
+#[derive(Debug, Clone)]
+struct Accumulator {
+    start_ms: u64,
+    count: u32,
+    min: [i32; 2],
+    max: [i32; 2],
+    sums: [i64; 2],
+}
+
+impl Accumulator {
+    fn new(start_ms: u64, values: [i32; 2]) -> Self {
+        Self {
+            start_ms,
+            count: 1,
+            min: values,
+            max: values,
+            sums: values.map(i64::from),
+        }
+    }
+
+    fn add(&mut self, values: [i32; 2]) {
+        self.count += 1;
+        for (i, value) in values.into_iter().enumerate() {
+            self.min[i] = self.min[i].min(value);
+            self.max[i] = self.max[i].max(value);
+            self.sums[i] += i64::from(value);
+        }
+    }
+
+    fn bucket(&self, duration_ms: u32) -> Bucket {
+        let count = i64::from(self.count);
+        Bucket {
+            start_ms: self.start_ms,
+            duration_ms,
+            count: self.count,
+            min: measurement(self.min),
+            max: measurement(self.max),
+            mean: measurement(self.sums.map(|sum| (sum / count) as i32)),
+        }
+    }
+}
+
+fn measurement(values: [i32; 2]) -> Measurement {
+    Measurement {
+        temperature: Temperature::from_millidegrees_celsius(values[0]),
+        humidity: Humidity::from_millipercent(values[1]),
+    }
+}
+
+/// Aggregates timestamped measurements into fixed time buckets.
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    duration_ms: u32,
+    current: Option<Accumulator>,
+}
+
+impl Aggregator {
+    /// One minute in milliseconds.
+    pub const ONE_MINUTE_MS: u32 = 60_000;
+    /// Fifteen minutes in milliseconds.
+    pub const FIFTEEN_MINUTES_MS: u32 = 15 * 60_000;
+    /// One hour in milliseconds.
+    pub const ONE_HOUR_MS: u32 = 60 * 60_000;
+
+    /// Create an aggregator with buckets of `duration_ms` milliseconds (at
+    /// least 1).
+    pub fn new(duration_ms: u32) -> Self {
+        Self {
+            duration_ms: duration_ms.max(1),
+            current: None,
+        }
+    }
+
+    /// Return the bucket duration in milliseconds.
+    pub fn duration_ms(&self) -> u32 {
+        self.duration_ms
+    }
+
+    /// Add a measurement taken at `timestamp_ms` (milliseconds of a
+    /// monotonic clock).
+    ///
+    /// If the measurement belongs to a later bucket than the previous
+    /// measurements, the completed bucket is returned. Measurements with a
+    /// timestamp before the current bucket are added to the current bucket.
+    pub fn add(&mut self, measurement: Measurement, timestamp_ms: u64) -> Option<Bucket> {
+        let values = [
+            measurement.temperature.as_millidegrees_celsius(),
+            measurement.humidity.as_millipercent(),
+        ];
+        let start_ms = timestamp_ms - timestamp_ms % u64::from(self.duration_ms);
+        match &mut self.current {
+            Some(current) if start_ms <= current.start_ms => {
+                current.add(values);
+                None
+            }
+            current => current
+                .replace(Accumulator::new(start_ms, values))
+                .map(|completed| completed.bucket(self.duration_ms)),
+        }
+    }
+
+    /// Add a measurement and write the completed bucket (if any) to `sink`.
+    ///
+    /// See [`add`](Aggregator::add).
+    pub fn add_to<S: BucketSink>(
+        &mut self,
+        measurement: Measurement,
+        timestamp_ms: u64,
+        sink: &mut S,
+    ) -> Result<(), S::Error> {
+        match self.add(measurement, timestamp_ms) {
+            Some(bucket) => sink.write(&bucket),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the statistics of the current (incomplete) bucket.
+    pub fn current(&self) -> Option<Bucket> {
+        self.current
+            .as_ref()
+            .map(|current| current.bucket(self.duration_ms))
+    }
+
+    /// Complete the current bucket and return it, e.g. before shutting down.
+    pub fn flush(&mut self) -> Option<Bucket> {
+        self.current
+            .take()
+            .map(|current| current.bucket(self.duration_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        measurement([temperature, humidity])
+    }
+
+    #[test]
+    fn buckets() {
+        let mut aggregator = Aggregator::new(Aggregator::ONE_MINUTE_MS);
+        assert_eq!(aggregator.current(), None);
+        assert_eq!(aggregator.add(m(20_000, 40_000), 61_000), None);
+        assert_eq!(aggregator.add(m(22_000, 44_000), 90_000), None);
+        assert_eq!(aggregator.add(m(21_000, 39_000), 119_999), None);
+        assert_eq!(aggregator.current().unwrap().count, 3);
+
+        let bucket = aggregator.add(m(0, 0), 120_000).unwrap();
+        assert_eq!(
+            bucket,
+            Bucket {
+                start_ms: 60_000,
+                duration_ms: 60_000,
+                count: 3,
+                min: m(20_000, 39_000),
+                max: m(22_000, 44_000),
+                mean: m(21_000, 41_000),
+            }
+        );
+
+        // Empty buckets are skipped
+        let bucket = aggregator.add(m(0, 0), 500_000).unwrap();
+        assert_eq!(bucket.start_ms, 120_000);
+        assert_eq!(bucket.count, 1);
+
+        let bucket = aggregator.flush().unwrap();
+        assert_eq!(bucket.start_ms, 480_000);
+        assert_eq!(aggregator.flush(), None);
+    }
+
+    /// Measurements with earlier timestamps are added to the current bucket.
+    #[test]
+    fn earlier_timestamp() {
+        let mut aggregator = Aggregator::new(1_000);
+        aggregator.add(m(1, 1), 5_500);
+        assert_eq!(aggregator.add(m(3, 3), 4_900), None);
+        let bucket = aggregator.flush().unwrap();
+        assert_eq!(bucket.start_ms, 5_000);
+        assert_eq!(bucket.count, 2);
+        assert_eq!(bucket.mean, m(2, 2));
+    }
+
+    struct Log(Vec<Bucket>);
+
+    impl BucketSink for Log {
+        type Error = ();
+
+        fn write(&mut self, bucket: &Bucket) -> Result<(), ()> {
+            self.0.push(*bucket);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink() {
+        let mut aggregator = Aggregator::new(Aggregator::ONE_HOUR_MS);
+        let mut log = Log(vec![]);
+        for minute in 0..150 {
+            aggregator
+                .add_to(m(minute as i32, 0), minute * 60_000, &mut log)
+                .unwrap();
+        }
+        assert_eq!(log.0.len(), 2);
+        assert_eq!(log.0[0].count, 60);
+        assert_eq!(log.0[0].min, m(0, 0));
+        assert_eq!(log.0[0].max, m(59, 0));
+        assert_eq!(log.0[1].start_ms, 3_600_000);
+        assert_eq!(aggregator.current().unwrap().count, 30);
+    }
+
+    #[test]
+    fn zero_duration() {
+        let aggregator = Aggregator::new(0);
+        assert_eq!(aggregator.duration_ms(), 1);
+    }
+}
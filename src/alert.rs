@@ -0,0 +1,425 @@
+//! Software alerts with hysteresis.
+//!
+//! The SHTCx sensors don't have an alert pin. The [`AlertEvaluator`] checks
+//! every measurement against configurable thresholds for temperature,
+//! humidity and dew point and emits an [`AlertEvent`] whenever an alert is set
+//! or cleared.
+//!
+//! Every alert has separate thresholds for setting and clearing it
+//! (hysteresis), so it doesn't toggle when the value fluctuates around the
+//! threshold. Additionally, a minimum dwell time can be configured: A
+//! condition must persist for that long before the alert is set or cleared.
+//!
+//! ```
+//! use shtcx::alert::{AlertConfig, AlertEvaluator, AlertEvent, Thresholds};
+//! use shtcx::{Humidity, Measurement, Temperature};
+//!
+//! let config = AlertConfig {
+//!     // Set above 30 °C, clear below 28 °C
+//!     high_temperature: Some(Thresholds { set: 30_000, clear: 28_000 }),
+//!     dwell_ms: 10_000,
+//!     ..AlertConfig::default()
+//! };
+//! let mut alerts = AlertEvaluator::new(config);
+//! let hot = Measurement {
+//!     temperature: Temperature::from_millidegrees_celsius(31_000),
+//!     humidity: Humidity::from_millipercent(40_000),
+//! };
+//! assert_eq!(alerts.update(&hot, 0).next(), None);
+//! assert_eq!(alerts.update(&hot, 10_000).next(), Some(AlertEvent::HighTemperatureSet));
+//! ```
+
+use crate::Measurement;
+
+/// Thresholds of an alert in milli-units (m°C or 1/1000 %RH).
+///
+/// For high alerts, the alert is set when the value is at or above `set` and
+/// cleared when it is at or below `clear` (so `clear` should be lower than
+/// `set`). For low alerts, it's the other way around.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Threshold for setting the alert.
+    pub set: i32,
+    /// Threshold for clearing the alert.
+    pub clear: i32,
+}
+
+/// Configuration of an [`AlertEvaluator`].
+///
+/// Alerts without thresholds are disabled.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct AlertConfig {
+    /// Thresholds for the high temperature alert.
+    pub high_temperature: Option<Thresholds>,
+    /// Thresholds for the low temperature alert.
+    pub low_temperature: Option<Thresholds>,
+    /// Thresholds for the high humidity alert.
+    pub high_humidity: Option<Thresholds>,
+    /// Thresholds for the low humidity alert.
+    pub low_humidity: Option<Thresholds>,
+    /// Thresholds for the high dew point alert.
+    pub high_dew_point: Option<Thresholds>,
+    /// Thresholds for the low dew point alert.
+    pub low_dew_point: Option<Thresholds>,
+    /// Time in milliseconds a condition must persist before an alert is set
+    /// or cleared.
+    pub dwell_ms: u32,
+}
+
+/// An alert.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Alert {
+    /// The temperature is too high.
+    HighTemperature,
+    /// The temperature is too low.
+    LowTemperature,
+    /// The humidity is too high.
+    HighHumidity,
+    /// The humidity is too low.
+    LowHumidity,
+    /// The dew point is too high.
+    HighDewPoint,
+    /// The dew point is too low.
+    LowDewPoint,
+}
+
+impl Alert {
+    const ALL: [Alert; 6] = [
+        Alert::HighTemperature,
+        Alert::LowTemperature,
+        Alert::HighHumidity,
+        Alert::LowHumidity,
+        Alert::HighDewPoint,
+        Alert::LowDewPoint,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn is_high(self) -> bool {
+        matches!(
+            self,
+            Alert::HighTemperature | Alert::HighHumidity | Alert::HighDewPoint
+        )
+    }
+
+    fn thresholds(self, config: &AlertConfig) -> Option<Thresholds> {
+        match self {
+            Alert::HighTemperature => config.high_temperature,
+            Alert::LowTemperature => config.low_temperature,
+            Alert::HighHumidity => config.high_humidity,
+            Alert::LowHumidity => config.low_humidity,
+            Alert::HighDewPoint => config.high_dew_point,
+            Alert::LowDewPoint => config.low_dew_point,
+        }
+    }
+}
+
+/// An alert was set or cleared.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlertEvent {
+    /// The high temperature alert was set.
+    HighTemperatureSet,
+    /// The high temperature alert was cleared.
+    HighTemperatureCleared,
+    /// The low temperature alert was set.
+    LowTemperatureSet,
+    /// The low temperature alert was cleared.
+    LowTemperatureCleared,
+    /// The high humidity alert was set.
+    HighHumiditySet,
+    /// The high humidity alert was cleared.
+    HighHumidityCleared,
+    /// The low humidity alert was set.
+    LowHumiditySet,
+    /// The low humidity alert was cleared.
+    LowHumidityCleared,
+    /// The high dew point alert was set.
+    HighDewPointSet,
+    /// The high dew point alert was cleared.
+    HighDewPointCleared,
+    /// The low dew point alert was set.
+    LowDewPointSet,
+    /// The low dew point alert was cleared.
+    LowDewPointCleared,
+}
+
+impl AlertEvent {
+    fn new(alert: Alert, set: bool) -> Self {
+        match (alert, set) {
+            (Alert::HighTemperature, true) => AlertEvent::HighTemperatureSet,
+            (Alert::HighTemperature, false) => AlertEvent::HighTemperatureCleared,
+            (Alert::LowTemperature, true) => AlertEvent::LowTemperatureSet,
+            (Alert::LowTemperature, false) => AlertEvent::LowTemperatureCleared,
+            (Alert::HighHumidity, true) => AlertEvent::HighHumiditySet,
+            (Alert::HighHumidity, false) => AlertEvent::HighHumidityCleared,
+            (Alert::LowHumidity, true) => AlertEvent::LowHumiditySet,
+            (Alert::LowHumidity, false) => AlertEvent::LowHumidityCleared,
+            (Alert::HighDewPoint, true) => AlertEvent::HighDewPointSet,
+            (Alert::HighDewPoint, false) => AlertEvent::HighDewPointCleared,
+            (Alert::LowDewPoint, true) => AlertEvent::LowDewPointSet,
+            (Alert::LowDewPoint, false) => AlertEvent::LowDewPointCleared,
+        }
+    }
+
+    /// Return the alert that was set or cleared.
+    pub fn alert(&self) -> Alert {
+        Alert::ALL[*self as usize / 2]
+    }
+
+    /// Return whether the alert was set (`true`) or cleared (`false`).
+    pub fn is_set(&self) -> bool {
+        (*self as usize).is_multiple_of(2)
+    }
+}
+
+/// The events emitted for a single measurement.
+#[derive(Debug, Clone)]
+pub struct AlertEvents {
+    events: [Option<AlertEvent>; 6],
+    next: usize,
+}
+
+impl Iterator for AlertEvents {
+    type Item = AlertEvent;
+
+    fn next(&mut self) -> Option<AlertEvent> {
+        while self.next < self.events.len() {
+            self.next += 1;
+            if let Some(event) = self.events[self.next - 1] {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+/// State of a single alert.
+#[derive(Debug, Copy, Clone, Default)]
+struct AlertState {
+    active: bool,
+    /// Time since which the condition for changing the state is met.
+    pending_since_ms: Option<u64>,
+}
+
+/// Evaluates measurements against the configured alert thresholds.
+#[derive(Debug, Clone)]
+pub struct AlertEvaluator {
+    config: AlertConfig,
+    states: [AlertState; 6],
+}
+
+impl AlertEvaluator {
+    /// Create a new evaluator with all alerts cleared.
+    pub fn new(config: AlertConfig) -> Self {
+        Self {
+            config,
+            states: [AlertState::default(); 6],
+        }
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &AlertConfig {
+        &self.config
+    }
+
+    /// Return whether an alert is currently set.
+    pub fn is_active(&self, alert: Alert) -> bool {
+        self.states[alert.index()].active
+    }
+
+    /// Clear all alerts (without emitting events).
+    pub fn reset(&mut self) {
+        self.states = [AlertState::default(); 6];
+    }
+
+    /// Evaluate a measurement taken at `now_ms` (milliseconds of a monotonic
+    /// clock) and return the alerts that were set or cleared.
+    pub fn update(&mut self, measurement: &Measurement, now_ms: u64) -> AlertEvents {
+        let mut events = AlertEvents {
+            events: [None; 6],
+            next: 0,
+        };
+        let uses_dew_point =
+            self.config.high_dew_point.is_some() || self.config.low_dew_point.is_some();
+        let dew_point = if uses_dew_point {
+            measurement.dew_point().as_millidegrees_celsius()
+        } else {
+            0
+        };
+
+        for alert in Alert::ALL {
+            let state = &mut self.states[alert.index()];
+            let Some(thresholds) = alert.thresholds(&self.config) else {
+                *state = AlertState::default();
+                continue;
+            };
+            let value = match alert {
+                Alert::HighTemperature | Alert::LowTemperature => {
+                    measurement.temperature.as_millidegrees_celsius()
+                }
+                Alert::HighHumidity | Alert::LowHumidity => measurement.humidity.as_millipercent(),
+                Alert::HighDewPoint | Alert::LowDewPoint => dew_point,
+            };
+            let change = match (state.active, alert.is_high()) {
+                (false, true) => value >= thresholds.set,
+                (true, true) => value <= thresholds.clear,
+                (false, false) => value <= thresholds.set,
+                (true, false) => value >= thresholds.clear,
+            };
+            if !change {
+                state.pending_since_ms = None;
+                continue;
+            }
+            let pending_since_ms = *state.pending_since_ms.get_or_insert(now_ms);
+            if now_ms.saturating_sub(pending_since_ms) >= u64::from(self.config.dwell_ms) {
+                state.active = !state.active;
+                state.pending_since_ms = None;
+                events.events[alert.index()] = Some(AlertEvent::new(alert, state.active));
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Humidity, Temperature};
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    fn events(
+        evaluator: &mut AlertEvaluator,
+        measurement: Measurement,
+        now_ms: u64,
+    ) -> Vec<AlertEvent> {
+        evaluator.update(&measurement, now_ms).collect()
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut alerts = AlertEvaluator::new(AlertConfig {
+            high_temperature: Some(Thresholds {
+                set: 30_000,
+                clear: 28_000,
+            }),
+            low_humidity: Some(Thresholds {
+                set: 20_000,
+                clear: 25_000,
+            }),
+            ..AlertConfig::default()
+        });
+        assert_eq!(events(&mut alerts, m(29_000, 30_000), 0), []);
+        assert_eq!(
+            events(&mut alerts, m(30_000, 20_000), 1),
+            [AlertEvent::HighTemperatureSet, AlertEvent::LowHumiditySet]
+        );
+        assert!(alerts.is_active(Alert::HighTemperature));
+        assert!(alerts.is_active(Alert::LowHumidity));
+        // Between the thresholds, nothing changes
+        assert_eq!(events(&mut alerts, m(29_000, 24_000), 2), []);
+        assert_eq!(events(&mut alerts, m(31_000, 19_000), 3), []);
+        assert_eq!(
+            events(&mut alerts, m(28_000, 24_000), 4),
+            [AlertEvent::HighTemperatureCleared]
+        );
+        assert_eq!(
+            events(&mut alerts, m(28_000, 25_000), 5),
+            [AlertEvent::LowHumidityCleared]
+        );
+        assert!(!alerts.is_active(Alert::HighTemperature));
+        assert!(!alerts.is_active(Alert::LowHumidity));
+    }
+
+    #[test]
+    fn dwell_time() {
+        let mut alerts = AlertEvaluator::new(AlertConfig {
+            low_temperature: Some(Thresholds {
+                set: 5_000,
+                clear: 6_000,
+            }),
+            dwell_ms: 1_000,
+            ..AlertConfig::default()
+        });
+        assert_eq!(events(&mut alerts, m(4_000, 0), 0), []);
+        assert_eq!(events(&mut alerts, m(4_000, 0), 999), []);
+        // The condition is interrupted, the dwell time starts again
+        assert_eq!(events(&mut alerts, m(5_500, 0), 1_000), []);
+        assert_eq!(events(&mut alerts, m(4_000, 0), 1_500), []);
+        assert_eq!(events(&mut alerts, m(4_000, 0), 2_000), []);
+        assert_eq!(
+            events(&mut alerts, m(4_000, 0), 2_500),
+            [AlertEvent::LowTemperatureSet]
+        );
+        assert_eq!(events(&mut alerts, m(7_000, 0), 3_000), []);
+        assert_eq!(
+            events(&mut alerts, m(7_000, 0), 4_000),
+            [AlertEvent::LowTemperatureCleared]
+        );
+    }
+
+    #[test]
+    fn dew_point() {
+        let mut alerts = AlertEvaluator::new(AlertConfig {
+            high_dew_point: Some(Thresholds {
+                set: 15_000,
+                clear: 14_000,
+            }),
+            ..AlertConfig::default()
+        });
+        // Dew point ~13.9 °C
+        assert_eq!(events(&mut alerts, m(25_000, 50_000), 0), []);
+        // Dew point ~16.7 °C
+        assert_eq!(
+            events(&mut alerts, m(25_000, 60_000), 1),
+            [AlertEvent::HighDewPointSet]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut alerts = AlertEvaluator::new(AlertConfig {
+            high_humidity: Some(Thresholds {
+                set: 80_000,
+                clear: 70_000,
+            }),
+            ..AlertConfig::default()
+        });
+        assert_eq!(
+            events(&mut alerts, m(0, 90_000), 0),
+            [AlertEvent::HighHumiditySet]
+        );
+        alerts.reset();
+        assert!(!alerts.is_active(Alert::HighHumidity));
+        assert_eq!(
+            events(&mut alerts, m(0, 90_000), 1),
+            [AlertEvent::HighHumiditySet]
+        );
+    }
+
+    #[test]
+    fn event_properties() {
+        assert_eq!(
+            AlertEvent::HighTemperatureSet.alert(),
+            Alert::HighTemperature
+        );
+        assert!(AlertEvent::HighTemperatureSet.is_set());
+        assert_eq!(AlertEvent::LowDewPointCleared.alert(), Alert::LowDewPoint);
+        assert!(!AlertEvent::LowDewPointCleared.is_set());
+        for alert in Alert::ALL {
+            for set in [true, false] {
+                let event = AlertEvent::new(alert, set);
+                assert_eq!(event.alert(), alert);
+                assert_eq!(event.is_set(), set);
+            }
+        }
+    }
+}
@@ -0,0 +1,554 @@
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{I2c, SevenBitAddress},
+};
+
+use crate::{
+    sensor_class::Sht2Gen, Error, Humidity, LowPower, Measurement, MeasurementKind,
+    PendingMeasurement, PollResult, PowerMode, ShtC3, ShtCx, Temperature,
+};
+
+/// When the sensor is put back to sleep by [`AutoSleep`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SleepPolicy {
+    /// Put the sensor to sleep after every command.
+    Always,
+    /// Put the sensor to sleep once it has been idle for the given number of
+    /// milliseconds (see [`AutoSleep::update`]).
+    AfterIdle {
+        /// Idle time in milliseconds.
+        idle_ms: u32,
+    },
+    /// Never put the sensor to sleep (but still wake it up if needed).
+    Never,
+}
+
+/// SHTC3 driver with automatic power management.
+///
+/// The driver keeps track of whether the sensor is asleep, wakes it up before
+/// every command and puts it back to sleep according to the configured
+/// [`SleepPolicy`].
+///
+/// Commands without a wrapper method can be run with
+/// [`with_awake`](AutoSleep::with_awake). Settings that don't access the
+/// sensor (e.g. the calibration or the statistics) are available through
+/// [`inner`](AutoSleep::inner) and [`inner_mut`](AutoSleep::inner_mut).
+///
+/// Non-blocking measurements are started with the `start_*` methods and read
+/// with [`finish`](AutoSleep::finish) or [`poll`](AutoSleep::poll). The sensor
+/// is kept awake until the result has been read.
+///
+/// Create an instance with [`ShtCx::into_auto_sleep`]. Since the state of the
+/// sensor is unknown at that point, it is assumed to be asleep.
+#[derive(Debug)]
+pub struct AutoSleep<I2C> {
+    sht: ShtC3<I2C>,
+    policy: SleepPolicy,
+    asleep: bool,
+    /// Whether a non-blocking measurement is in progress.
+    measuring: bool,
+    /// Time since which the sensor is idle (for [`SleepPolicy::AfterIdle`]).
+    idle_since_ms: Option<u64>,
+}
+
+impl<I2C> ShtCx<Sht2Gen, I2C>
+where
+    I2C: I2c<SevenBitAddress>,
+{
+    /// Enable automatic power management with the given policy.
+    pub fn into_auto_sleep(self, policy: SleepPolicy) -> AutoSleep<I2C> {
+        AutoSleep {
+            sht: self,
+            policy,
+            asleep: true,
+            measuring: false,
+            idle_since_ms: None,
+        }
+    }
+}
+
+impl<I2C> AutoSleep<I2C>
+where
+    I2C: I2c<SevenBitAddress>,
+{
+    /// Disable automatic power management, return the driver.
+    ///
+    /// The sensor is left in its current state (see
+    /// [`is_asleep`](AutoSleep::is_asleep)).
+    pub fn into_inner(self) -> ShtC3<I2C> {
+        self.sht
+    }
+
+    /// Return a reference to the driver.
+    pub fn inner(&self) -> &ShtC3<I2C> {
+        &self.sht
+    }
+
+    /// Return a mutable reference to the driver.
+    ///
+    /// This is meant for settings that don't access the sensor, like the
+    /// calibration. Use [`with_awake`](AutoSleep::with_awake) for commands,
+    /// so that the sleep state is tracked.
+    pub fn inner_mut(&mut self) -> &mut ShtC3<I2C> {
+        &mut self.sht
+    }
+
+    /// Return whether the sensor is currently asleep.
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    /// Return the sleep policy.
+    pub fn policy(&self) -> SleepPolicy {
+        self.policy
+    }
+
+    /// Change the sleep policy.
+    pub fn set_policy(&mut self, policy: SleepPolicy) {
+        self.policy = policy;
+        self.idle_since_ms = None;
+    }
+
+    /// Put the sensor to sleep if it is awake.
+    ///
+    /// A measurement that is in progress is aborted.
+    pub fn sleep(&mut self) -> Result<(), Error<I2C::Error>> {
+        if !self.asleep {
+            self.sht.sleep()?;
+            self.asleep = true;
+        }
+        self.measuring = false;
+        self.idle_since_ms = None;
+        Ok(())
+    }
+
+    /// Wake up the sensor if it is asleep.
+    pub fn wakeup(&mut self, delay: &mut impl DelayNs) -> Result<(), Error<I2C::Error>> {
+        if self.asleep {
+            self.sht.wakeup(delay)?;
+            self.asleep = false;
+        }
+        Ok(())
+    }
+
+    /// Apply the [`SleepPolicy::AfterIdle`] policy.
+    ///
+    /// Call this periodically with the current time of a monotonic clock in
+    /// milliseconds. The idle time starts with the first call after the last
+    /// command. While a non-blocking measurement is in progress, or for the
+    /// other policies, this does nothing.
+    pub fn update(&mut self, now_ms: u64) -> Result<(), Error<I2C::Error>> {
+        let SleepPolicy::AfterIdle { idle_ms } = self.policy else {
+            return Ok(());
+        };
+        if self.asleep || self.measuring {
+            return Ok(());
+        }
+        let idle_since_ms = *self.idle_since_ms.get_or_insert(now_ms);
+        if now_ms.saturating_sub(idle_since_ms) >= u64::from(idle_ms) {
+            self.sleep()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the sleep policy after a command.
+    fn command_done(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.idle_since_ms = None;
+        match self.policy {
+            SleepPolicy::Always => self.sleep(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Run a command with the sensor awake, then apply the sleep policy.
+    ///
+    /// The command gets the driver and the delay. It must not put the sensor
+    /// to sleep or start a non-blocking measurement (use the `start_*`
+    /// methods for that), since the sensor may be put to sleep afterwards.
+    ///
+    /// ```no_run
+    /// # use linux_embedded_hal::{Delay, I2cdev};
+    /// use shtcx::{PowerMode, SleepPolicy};
+    ///
+    /// # let sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+    /// let mut sht = sht.into_auto_sleep(SleepPolicy::Always);
+    /// let annotated = sht
+    ///     .with_awake(&mut Delay, |sht, delay| {
+    ///         sht.measure_with_metadata(PowerMode::NormalMode, delay)
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn with_awake<D, T>(
+        &mut self,
+        delay: &mut D,
+        command: impl FnOnce(&mut ShtC3<I2C>, &mut D) -> Result<T, Error<I2C::Error>>,
+    ) -> Result<T, Error<I2C::Error>>
+    where
+        D: DelayNs,
+    {
+        self.wakeup(delay)?;
+        let result = command(&mut self.sht, delay);
+        let slept = self.command_done();
+        let value = result?;
+        slept?;
+        Ok(value)
+    }
+
+    /// Wake up the sensor and start a non-blocking measurement.
+    fn start<T>(
+        &mut self,
+        delay: &mut impl DelayNs,
+        start: impl FnOnce(&mut ShtC3<I2C>) -> Result<PendingMeasurement<T>, Error<I2C::Error>>,
+    ) -> Result<PendingMeasurement<T>, Error<I2C::Error>> {
+        self.wakeup(delay)?;
+        match start(&mut self.sht) {
+            Ok(pending) => {
+                self.measuring = true;
+                self.idle_since_ms = None;
+                Ok(pending)
+            }
+            Err(e) => {
+                // The error of the measurement command is more relevant
+                let _ = self.command_done();
+                Err(e)
+            }
+        }
+    }
+
+    /// Start a combined temperature / humidity measurement.
+    ///
+    /// See [`ShtCx::start_measurement`]. The sensor stays awake until the
+    /// result is read with [`finish`](AutoSleep::finish) or
+    /// [`poll`](AutoSleep::poll).
+    pub fn start_measurement(
+        &mut self,
+        mode: PowerMode,
+        now_us: u64,
+        delay: &mut impl DelayNs,
+    ) -> Result<PendingMeasurement<Measurement>, Error<I2C::Error>> {
+        self.start(delay, |sht| sht.start_measurement(mode, now_us))
+    }
+
+    /// Start a temperature measurement.
+    ///
+    /// See [`AutoSleep::start_measurement`].
+    pub fn start_temperature_measurement(
+        &mut self,
+        mode: PowerMode,
+        now_us: u64,
+        delay: &mut impl DelayNs,
+    ) -> Result<PendingMeasurement<Temperature>, Error<I2C::Error>> {
+        self.start(delay, |sht| sht.start_temperature_measurement(mode, now_us))
+    }
+
+    /// Start a humidity measurement.
+    ///
+    /// See [`AutoSleep::start_measurement`].
+    pub fn start_humidity_measurement(
+        &mut self,
+        mode: PowerMode,
+        now_us: u64,
+        delay: &mut impl DelayNs,
+    ) -> Result<PendingMeasurement<Humidity>, Error<I2C::Error>> {
+        self.start(delay, |sht| sht.start_humidity_measurement(mode, now_us))
+    }
+
+    /// Read the result of a non-blocking measurement, then apply the sleep
+    /// policy.
+    ///
+    /// See [`PendingMeasurement::finish`].
+    pub fn finish<T: MeasurementKind>(
+        &mut self,
+        pending: PendingMeasurement<T>,
+    ) -> Result<T, Error<I2C::Error>> {
+        let result = pending.finish(&mut self.sht);
+        self.measuring = false;
+        let slept = self.command_done();
+        let value = result?;
+        slept?;
+        Ok(value)
+    }
+
+    /// Read the result of a non-blocking measurement if it is available.
+    ///
+    /// While the measurement is in progress, the token is returned together
+    /// with [`WouldBlock`](nb::Error::WouldBlock) and the sensor stays awake.
+    /// Otherwise the sleep policy is applied. If putting the sensor to sleep
+    /// fails, it stays awake (see [`is_asleep`](AutoSleep::is_asleep)). See
+    /// [`PendingMeasurement::poll`].
+    pub fn poll<T: MeasurementKind>(
+        &mut self,
+        pending: PendingMeasurement<T>,
+    ) -> PollResult<T, I2C::Error> {
+        let result = pending.poll(&mut self.sht);
+        if !matches!(result, Err((_, nb::Error::WouldBlock))) {
+            self.measuring = false;
+            // The result has been read, so the error of the sleep command
+            // can't be returned with it
+            let _ = self.command_done();
+        }
+        result
+    }
+
+    /// Run a temperature/humidity measurement and return the combined result.
+    ///
+    /// See [`ShtCx::measure`].
+    pub fn measure(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Measurement, Error<I2C::Error>> {
+        self.with_awake(delay, |sht, delay| sht.measure(mode, delay))
+    }
+
+    /// Run a temperature measurement and return the result.
+    ///
+    /// See [`ShtCx::measure_temperature`].
+    pub fn measure_temperature(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Temperature, Error<I2C::Error>> {
+        self.with_awake(delay, |sht, delay| sht.measure_temperature(mode, delay))
+    }
+
+    /// Run a humidity measurement and return the result.
+    ///
+    /// See [`ShtCx::measure_humidity`].
+    pub fn measure_humidity(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Humidity, Error<I2C::Error>> {
+        self.with_awake(delay, |sht, delay| sht.measure_humidity(mode, delay))
+    }
+
+    /// Return the raw ID register.
+    pub fn raw_id_register(&mut self, delay: &mut impl DelayNs) -> Result<u16, Error<I2C::Error>> {
+        self.with_awake(delay, |sht, _| sht.raw_id_register())
+    }
+
+    /// Return the 7-bit device identifier.
+    pub fn device_identifier(&mut self, delay: &mut impl DelayNs) -> Result<u8, Error<I2C::Error>> {
+        self.with_awake(delay, |sht, _| sht.device_identifier())
+    }
+
+    /// Trigger a soft reset.
+    ///
+    /// See [`ShtCx::reset`].
+    pub fn reset(&mut self, delay: &mut impl DelayNs) -> Result<(), Error<I2C::Error>> {
+        self.with_awake(delay, |sht, delay| sht.reset(delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{calibration::Calibration, shtc3};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    fn wakeup() -> Transaction {
+        Transaction::write(SHT_ADDR, vec![0x35, 0x17])
+    }
+
+    fn sleep() -> Transaction {
+        Transaction::write(SHT_ADDR, vec![0xb0, 0x98])
+    }
+
+    fn measurement() -> [Transaction; 2] {
+        [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+        ]
+    }
+
+    #[test]
+    fn always() {
+        let mut expectations = vec![wakeup()];
+        expectations.extend(measurement());
+        expectations.push(sleep());
+        expectations.push(wakeup());
+        expectations.extend(measurement());
+        expectations.push(sleep());
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Always);
+        assert!(sht.is_asleep());
+        for _ in 0..2 {
+            let measurement = sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+            assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+            assert!(sht.is_asleep());
+        }
+        sht.into_inner().destroy().done();
+    }
+
+    #[test]
+    fn never() {
+        let mut expectations = vec![wakeup()];
+        expectations.extend(measurement());
+        expectations.extend(measurement());
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Never);
+        sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        assert!(!sht.is_asleep());
+        sht.update(1_000_000).unwrap();
+        assert!(!sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+
+    #[test]
+    fn after_idle() {
+        let mut expectations = vec![wakeup()];
+        expectations.extend(measurement());
+        expectations.extend(measurement());
+        expectations.push(sleep());
+        let mut sht = shtc3(I2cMock::new(&expectations))
+            .into_auto_sleep(SleepPolicy::AfterIdle { idle_ms: 100 });
+        sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        sht.update(1_000).unwrap();
+        sht.update(1_050).unwrap();
+        // Another command resets the idle time
+        sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        sht.update(1_120).unwrap();
+        sht.update(1_200).unwrap();
+        assert!(!sht.is_asleep());
+        sht.update(1_220).unwrap();
+        assert!(sht.is_asleep());
+        sht.update(1_300).unwrap();
+        sht.into_inner().destroy().done();
+    }
+
+    #[test]
+    fn other_commands() {
+        let expectations = [
+            wakeup(),
+            Transaction::write(SHT_ADDR, vec![0xef, 0xc8]),
+            Transaction::read(SHT_ADDR, vec![0x08, 0x87, 0x5b]),
+            sleep(),
+            wakeup(),
+            Transaction::write(SHT_ADDR, vec![0x80, 0x5d]),
+            sleep(),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Always);
+        assert_eq!(sht.device_identifier(&mut NoopDelay).unwrap(), 0x47);
+        sht.reset(&mut NoopDelay).unwrap();
+        sht.into_inner().destroy().done();
+    }
+
+    #[test]
+    fn with_awake() {
+        let mut expectations = vec![wakeup()];
+        expectations.extend(measurement());
+        expectations.push(sleep());
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Always);
+        sht.inner_mut().set_calibration(Calibration {
+            temperature_offset: -1_000,
+            ..Calibration::NONE
+        });
+        assert!(sht.inner().calibration().corrects_temperature());
+        let annotated = sht
+            .with_awake(&mut NoopDelay, |sht, delay| {
+                sht.measure_with_metadata(PowerMode::LowPower, delay)
+            })
+            .unwrap();
+        assert_eq!(
+            annotated.measurement.temperature.as_millidegrees_celsius(),
+            22_730
+        );
+        assert!(sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+
+    /// The sensor stays awake until the result of a non-blocking measurement
+    /// has been read.
+    #[test]
+    fn non_blocking() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        let [start, read] = measurement();
+        let expectations = [
+            wakeup(),
+            start.clone(),
+            Transaction::read(SHT_ADDR, vec![0; 6]).with_error(nack),
+            read.clone(),
+            sleep(),
+            wakeup(),
+            start,
+            read,
+            sleep(),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Always);
+        let pending = sht
+            .start_measurement(PowerMode::LowPower, 0, &mut NoopDelay)
+            .unwrap();
+        assert!(!sht.is_asleep());
+        let (pending, err) = sht.poll(pending).unwrap_err();
+        assert_eq!(err, nb::Error::WouldBlock);
+        assert!(!sht.is_asleep());
+        let measurement = sht.poll(pending).unwrap();
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+        assert!(sht.is_asleep());
+
+        let pending = sht
+            .start_measurement(PowerMode::LowPower, 0, &mut NoopDelay)
+            .unwrap();
+        assert!(sht.finish(pending).is_ok());
+        assert!(sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+
+    /// The idle time doesn't elapse during a non-blocking measurement.
+    #[test]
+    fn non_blocking_after_idle() {
+        let mut expectations = vec![wakeup()];
+        expectations.extend(measurement());
+        expectations.push(sleep());
+        let mut sht = shtc3(I2cMock::new(&expectations))
+            .into_auto_sleep(SleepPolicy::AfterIdle { idle_ms: 100 });
+        let pending = sht
+            .start_measurement(PowerMode::LowPower, 0, &mut NoopDelay)
+            .unwrap();
+        sht.update(1_000).unwrap();
+        sht.update(1_200).unwrap();
+        assert!(!sht.is_asleep());
+        sht.finish(pending).unwrap();
+        sht.update(1_300).unwrap();
+        sht.update(1_400).unwrap();
+        assert!(sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+
+    /// Command errors are returned, but the sensor is still put to sleep.
+    #[test]
+    fn command_error() {
+        let expectations = [
+            wakeup(),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]).with_error(ErrorKind::Other),
+            sleep(),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Always);
+        let err = sht
+            .measure(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap_err();
+        assert_eq!(err, Error::I2c(ErrorKind::Other));
+        assert!(sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+
+    /// If the wakeup fails, the sensor is still considered asleep.
+    #[test]
+    fn wakeup_error() {
+        let expectations = [wakeup().with_error(ErrorKind::Other), wakeup()];
+        let mut sht = shtc3(I2cMock::new(&expectations)).into_auto_sleep(SleepPolicy::Never);
+        assert!(sht.measure(PowerMode::LowPower, &mut NoopDelay).is_err());
+        assert!(sht.is_asleep());
+        sht.wakeup(&mut NoopDelay).unwrap();
+        assert!(!sht.is_asleep());
+        sht.into_inner().destroy().done();
+    }
+}
@@ -0,0 +1,289 @@
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{I2c, SevenBitAddress},
+};
+
+use crate::{
+    Error, Humidity, Measurement, MeasurementDuration, MeasurementOrder, PowerMode, RawMeasurement,
+    ShtCx, ShtSensor, Temperature,
+};
+
+/// The result of an averaged measurement.
+///
+/// See [`ShtCx::measure_averaged`]. The minimum and maximum are determined
+/// separately for temperature and humidity, so `min` and `max` usually don't
+/// correspond to a single measurement.
+///
+/// The [calibration](ShtCx::set_calibration) of the driver is applied to
+/// `mean`, `min` and `max`, but not to the standard deviations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AveragedMeasurement {
+    /// The mean temperature and humidity.
+    pub mean: Measurement,
+    /// The minimum temperature and humidity.
+    pub min: Measurement,
+    /// The maximum temperature and humidity.
+    pub max: Measurement,
+    /// Standard deviation of the temperature in milli-degrees celsius.
+    pub temperature_std_dev: u32,
+    /// Standard deviation of the humidity in 1/1000 %RH.
+    pub humidity_std_dev: u32,
+    /// Number of measurements the statistics are based on (after trimming).
+    pub samples: u8,
+}
+
+impl AveragedMeasurement {
+    /// Maximum number of measurements that can be averaged.
+    pub const MAX_SAMPLES: u8 = 32;
+}
+
+/// Statistics of a series of raw values.
+struct RawStatistics {
+    sum: u32,
+    min: u16,
+    max: u16,
+    /// `n² · variance`
+    scaled_variance: u64,
+}
+
+impl RawStatistics {
+    fn new(values: &[u16]) -> Self {
+        let n = values.len() as u64;
+        let sum: u32 = values.iter().map(|&v| u32::from(v)).sum();
+        let sum_sq: u64 = values.iter().map(|&v| u64::from(v) * u64::from(v)).sum();
+        Self {
+            sum,
+            min: values.iter().copied().min().unwrap_or(0),
+            max: values.iter().copied().max().unwrap_or(0),
+            scaled_variance: n * sum_sq - u64::from(sum) * u64::from(sum),
+        }
+    }
+
+    /// Return the standard deviation, converted with the slope `factor / 2^13`
+    /// of the conversion formula.
+    fn std_dev(&self, n: usize, factor: u128) -> u32 {
+        let n = n as u128;
+        let variance = u128::from(self.scaled_variance) * factor * factor / (n * n * (1 << 26));
+        variance.isqrt() as u32
+    }
+}
+
+/// Averaged measurements.
+impl<S, I2C> ShtCx<S, I2C>
+where
+    S: ShtSensor + MeasurementDuration,
+    I2C: I2c<SevenBitAddress>,
+{
+    /// Run `n` temperature/humidity measurements and return their mean,
+    /// minimum, maximum and standard deviation.
+    ///
+    /// The raw values are averaged before conversion, so the mean is more
+    /// precise than the mean of the converted values. `n` is clamped to
+    /// `1..=`[`AveragedMeasurement::MAX_SAMPLES`].
+    ///
+    /// This is a blocking function call.
+    pub fn measure_averaged(
+        &mut self,
+        mode: PowerMode,
+        n: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<AveragedMeasurement, Error<I2C::Error>> {
+        self.measure_averaged_trimmed(mode, n, 0, delay)
+    }
+
+    /// Run `n` temperature/humidity measurements and return the statistics
+    /// of the trimmed series.
+    ///
+    /// Like [`measure_averaged`](ShtCx::measure_averaged), but the `trim`
+    /// lowest and `trim` highest values of temperature and humidity are
+    /// discarded before calculating the statistics, to reject outliers.
+    /// `trim` is clamped so that at least one measurement remains.
+    pub fn measure_averaged_trimmed(
+        &mut self,
+        mode: PowerMode,
+        n: u8,
+        trim: u8,
+        delay: &mut impl DelayNs,
+    ) -> Result<AveragedMeasurement, Error<I2C::Error>> {
+        let n = usize::from(n.clamp(1, AveragedMeasurement::MAX_SAMPLES));
+        let trim = usize::from(trim).min((n - 1) / 2);
+
+        let mut temperatures = [0u16; AveragedMeasurement::MAX_SAMPLES as usize];
+        let mut humidities = [0u16; AveragedMeasurement::MAX_SAMPLES as usize];
+        for i in 0..n {
+            let RawMeasurement {
+                temperature,
+                humidity,
+            } = self.measure_raw(mode, delay)?;
+            temperatures[i] = temperature;
+            humidities[i] = humidity;
+        }
+
+        let temperatures = &mut temperatures[..n];
+        let humidities = &mut humidities[..n];
+        if trim > 0 {
+            temperatures.sort_unstable();
+            humidities.sort_unstable();
+        }
+        let count = n - 2 * trim;
+        let temperature = RawStatistics::new(&temperatures[trim..n - trim]);
+        let humidity = RawStatistics::new(&humidities[trim..n - trim]);
+
+        let calibration = self.calibration;
+        Ok(AveragedMeasurement {
+            mean: calibration.apply(Measurement {
+                temperature: Temperature::from_raw_mean(temperature.sum, count as u32),
+                humidity: Humidity::from_raw_mean(humidity.sum, count as u32),
+            }),
+            min: calibration.apply(
+                RawMeasurement {
+                    temperature: temperature.min,
+                    humidity: humidity.min,
+                }
+                .into(),
+            ),
+            max: calibration.apply(
+                RawMeasurement {
+                    temperature: temperature.max,
+                    humidity: humidity.max,
+                }
+                .into(),
+            ),
+            temperature_std_dev: temperature.std_dev(count, 21875),
+            humidity_std_dev: humidity.std_dev(count, 12500),
+            samples: count as u8,
+        })
+    }
+
+    /// Run a temperature/humidity measurement and return the raw result.
+    fn measure_raw(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<RawMeasurement, Error<I2C::Error>> {
+        self.start_measure_partial(mode, MeasurementOrder::TemperatureFirst)?;
+        self.wait_for_measurement(mode, delay);
+        self.get_raw_measurement_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{crc::crc8, shtc3};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    /// Expected transactions for a low power measurement with the given raw
+    /// results.
+    fn measurement(temperature: u16, humidity: u16) -> [Transaction; 2] {
+        let t = temperature.to_be_bytes();
+        let h = humidity.to_be_bytes();
+        [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![t[0], t[1], crc8(&t), h[0], h[1], crc8(&h)]),
+        ]
+    }
+
+    #[test]
+    fn averaged() {
+        let mut expectations = vec![];
+        for (t, h) in [(0x6400, 0xa100), (0x6402, 0xa100), (0x6404, 0xa103)] {
+            expectations.extend(measurement(t, h));
+        }
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let result = sht
+            .measure_averaged(PowerMode::LowPower, 3, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(result.samples, 3);
+        assert_eq!(
+            result.mean.temperature,
+            Temperature::from_raw_mean(3 * 0x6402, 3)
+        );
+        assert_eq!(result.mean.humidity.as_millipercent(), 62_892);
+        assert_eq!(result.min.temperature, Temperature::from_raw(0x6400));
+        assert_eq!(result.max.temperature, Temperature::from_raw(0x6404));
+        assert_eq!(result.min.humidity, Humidity::from_raw(0xa100));
+        assert_eq!(result.max.humidity, Humidity::from_raw(0xa103));
+        // sqrt(8/3) raw steps of 2.67 m°C
+        assert_eq!(result.temperature_std_dev, 4);
+        // sqrt(2) raw steps of 1.53 m%RH
+        assert_eq!(result.humidity_std_dev, 2);
+        sht.destroy().done();
+    }
+
+    /// The mean is calculated from the raw values, not the converted ones.
+    #[test]
+    fn no_rounding_loss() {
+        let mut expectations = vec![];
+        for t in [0x6400, 0x6401] {
+            expectations.extend(measurement(t, 0));
+        }
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let result = sht
+            .measure_averaged(PowerMode::LowPower, 2, &mut NoopDelay)
+            .unwrap();
+        let min = result.min.temperature.as_millidegrees_celsius();
+        let max = result.max.temperature.as_millidegrees_celsius();
+        assert_eq!(max - min, 3);
+        assert_eq!(result.mean.temperature.as_millidegrees_celsius(), min + 1);
+        sht.destroy().done();
+    }
+
+    #[test]
+    fn trimmed() {
+        let mut expectations = vec![];
+        for (t, h) in [
+            (0x6400, 0xa100),
+            (0xffff, 0xa100),
+            (0x6400, 0x0000),
+            (0x6400, 0xa100),
+            (0x6400, 0xa100),
+        ] {
+            expectations.extend(measurement(t, h));
+        }
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let result = sht
+            .measure_averaged_trimmed(PowerMode::LowPower, 5, 1, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(result.samples, 3);
+        assert_eq!(result.mean.temperature, Temperature::from_raw(0x6400));
+        assert_eq!(result.mean.humidity, Humidity::from_raw(0xa100));
+        assert_eq!(result.min, result.max);
+        assert_eq!(result.temperature_std_dev, 0);
+        assert_eq!(result.humidity_std_dev, 0);
+        sht.destroy().done();
+    }
+
+    /// The number of samples and the trimming are clamped.
+    #[test]
+    fn clamped() {
+        let expectations = measurement(0x6400, 0xa100);
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let result = sht
+            .measure_averaged_trimmed(PowerMode::LowPower, 0, 3, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(result.samples, 1);
+        assert_eq!(result.mean.temperature, Temperature::from_raw(0x6400));
+        sht.destroy().done();
+    }
+
+    #[test]
+    fn error() {
+        let mut expectations = measurement(0x6400, 0xa100).to_vec();
+        expectations.push(Transaction::write(SHT_ADDR, vec![0x60, 0x9c]));
+        expectations.push(Transaction::read(SHT_ADDR, vec![0; 6]));
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let err = sht
+            .measure_averaged(PowerMode::LowPower, 4, &mut NoopDelay)
+            .unwrap_err();
+        assert_eq!(err, Error::Crc);
+        sht.destroy().done();
+    }
+}
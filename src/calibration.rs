@@ -0,0 +1,1063 @@
+//! Calibration of measurements.
+//!
+//! Sensors mounted near heat sources (e.g. a voltage regulator or a
+//! microcontroller) read consistently high. A [`Calibration`] corrects the
+//! temperature by a static offset and the humidity by a gain and an offset.
+//!
+//! The sensor measures the relative humidity at its own temperature. If the
+//! temperature is corrected, the humidity is re-derived at the compensated
+//! temperature (the absolute humidity stays the same), so temperature and
+//! humidity stay physically consistent.
+//!
+//! If the error of the sensor isn't constant, a [`CalibrationProfile`] with
+//! linear or piecewise-linear corrections can be fitted to measurements
+//! against a reference (see [`CalibrationProfile::fit`]). A [`Calibration`]
+//! is a special case of a profile (see the [`From`] implementation).
+//!
+//! A calibration or a profile can be set on the driver with
+//! [`ShtCx::set_calibration`](crate::ShtCx::set_calibration). It is then
+//! applied to all measurements returned by the driver. Raw measurements
+//! (e.g. [`get_raw_measurement_result`](crate::ShtCx::get_raw_measurement_result))
+//! are never modified.
+//!
+//! ```
+//! use shtcx::calibration::Calibration;
+//! use shtcx::{Humidity, Measurement, Temperature};
+//!
+//! // The sensor reads 2 °C too high
+//! let calibration = Calibration {
+//!     temperature_offset: -2_000,
+//!     ..Calibration::NONE
+//! };
+//! let measurement = calibration.apply(Measurement {
+//!     temperature: Temperature::from_millidegrees_celsius(25_000),
+//!     humidity: Humidity::from_millipercent(50_000),
+//! });
+//! assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_000);
+//! // The air is colder than the sensor, so the relative humidity is higher
+//! assert!(measurement.humidity.as_millipercent() > 50_000);
+//! ```
+
+use crate::{types::magnus_exponent, Humidity, Measurement, ShtCx, ShtSensor, Temperature};
+
+/// Static calibration of a sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Calibration {
+    /// Offset added to the temperature in m°C.
+    pub temperature_offset: i32,
+    /// Offset added to the humidity (after applying the gain) in 1/1000 %RH.
+    pub humidity_offset: i32,
+    /// Gain of the humidity in parts per million (`1_000_000` is a gain of
+    /// 1).
+    pub humidity_gain_ppm: i32,
+}
+
+impl Calibration {
+    /// The identity calibration that doesn't change measurements.
+    pub const NONE: Calibration = Calibration {
+        temperature_offset: 0,
+        humidity_offset: 0,
+        humidity_gain_ppm: 1_000_000,
+    };
+
+    /// Correct a measurement.
+    ///
+    /// The gain and offset of the humidity are applied first. Then the
+    /// humidity is converted from the measured to the compensated
+    /// temperature (using the Magnus formula for the saturation vapor
+    /// pressure) and clamped to 0..100 %RH.
+    pub fn apply(&self, measurement: Measurement) -> Measurement {
+        CalibrationProfile::from(*self).apply(measurement)
+    }
+
+    /// Correct a temperature.
+    pub fn apply_temperature(&self, temperature: Temperature) -> Temperature {
+        CalibrationProfile::from(*self).apply_temperature(temperature)
+    }
+
+    /// Correct a humidity with the gain and offset, clamped to 0..100 %RH.
+    ///
+    /// This doesn't re-derive the humidity at the compensated temperature,
+    /// which needs the measured temperature. If the temperature offset isn't
+    /// zero, use [`apply`](Calibration::apply) with a combined measurement
+    /// instead.
+    pub fn apply_humidity(&self, humidity: Humidity) -> Humidity {
+        CalibrationProfile::from(*self).apply_humidity(humidity)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Convert a relative humidity (in 1/1000 %RH) at the temperature `from` to
+/// the relative humidity with the same absolute humidity at the temperature
+/// `to`.
+fn rederive(humidity: i32, from: Temperature, to: Temperature) -> i32 {
+    if from == to {
+        return humidity;
+    }
+    let ratio = libm::expf(
+        magnus_exponent(from.as_degrees_celsius()) - magnus_exponent(to.as_degrees_celsius()),
+    );
+    libm::roundf(humidity as f32 * ratio) as i32
+}
+
+/// Divide and round to the nearest integer (`denominator` must be positive).
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+    (2 * numerator + denominator).div_euclid(2 * denominator)
+}
+
+/// Convert to `i32`, saturating at the bounds.
+fn saturate(value: i128) -> i32 {
+    value.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+/// Maximum number of breakpoints of a piecewise-linear [`Correction`].
+pub const MAX_BREAKPOINTS: usize = 8;
+
+/// A breakpoint of a piecewise-linear [`Correction`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// The value measured by the sensor.
+    pub measured: i32,
+    /// The corrected value.
+    pub reference: i32,
+}
+
+/// The breakpoints of a piecewise-linear [`Correction`], sorted by the
+/// measured value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Breakpoints {
+    len: u8,
+    points: [Breakpoint; MAX_BREAKPOINTS],
+}
+
+impl Breakpoints {
+    /// Create breakpoints.
+    ///
+    /// Return `None` if there are no or more than [`MAX_BREAKPOINTS`]
+    /// breakpoints, or if they are not sorted by strictly increasing measured
+    /// values.
+    pub fn new(breakpoints: &[Breakpoint]) -> Option<Self> {
+        if breakpoints.is_empty()
+            || breakpoints.len() > MAX_BREAKPOINTS
+            || breakpoints
+                .windows(2)
+                .any(|w| w[0].measured >= w[1].measured)
+        {
+            return None;
+        }
+        let mut points = [Breakpoint::default(); MAX_BREAKPOINTS];
+        points[..breakpoints.len()].copy_from_slice(breakpoints);
+        Some(Self {
+            len: breakpoints.len() as u8,
+            points,
+        })
+    }
+
+    /// Return the breakpoints.
+    pub fn as_slice(&self) -> &[Breakpoint] {
+        &self.points[..usize::from(self.len)]
+    }
+
+    fn apply(&self, value: i32) -> i32 {
+        let points = self.as_slice();
+        let offset = |point: &Breakpoint| {
+            saturate(i128::from(value) + i128::from(point.reference) - i128::from(point.measured))
+        };
+        let (first, last) = (&points[0], &points[points.len() - 1]);
+        if value <= first.measured {
+            return offset(first);
+        }
+        if value >= last.measured {
+            return offset(last);
+        }
+        let i = points.partition_point(|point| point.measured <= value);
+        let (a, b) = (points[i - 1], points[i]);
+        let interpolated = div_round(
+            (i128::from(value) - i128::from(a.measured))
+                * (i128::from(b.reference) - i128::from(a.reference)),
+            i128::from(b.measured) - i128::from(a.measured),
+        );
+        saturate(i128::from(a.reference) + interpolated)
+    }
+}
+
+/// Correction of a temperature (in m°C) or humidity (in 1/1000 %RH).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Correction {
+    /// `value * gain_ppm / 1_000_000 + offset`
+    Linear {
+        /// Gain in parts per million.
+        gain_ppm: i32,
+        /// Offset added after applying the gain.
+        offset: i32,
+    },
+    /// Linear interpolation between breakpoints. Below the first and above
+    /// the last breakpoint, the offset of that breakpoint is applied.
+    Piecewise(Breakpoints),
+}
+
+/// Serialized length of a [`Correction`]: Kind, number of breakpoints and
+/// the breakpoints (or gain and offset).
+const CORRECTION_LEN: usize = 2 + 8 * MAX_BREAKPOINTS;
+
+impl Correction {
+    /// The identity correction.
+    pub const NONE: Correction = Correction::Linear {
+        gain_ppm: 1_000_000,
+        offset: 0,
+    };
+
+    /// Correct a value.
+    pub fn apply(&self, value: i32) -> i32 {
+        match self {
+            Correction::Linear { gain_ppm, offset } => saturate(
+                div_round(i128::from(value) * i128::from(*gain_ppm), 1_000_000)
+                    + i128::from(*offset),
+            ),
+            Correction::Piecewise(breakpoints) => breakpoints.apply(value),
+        }
+    }
+
+    /// Fit a linear correction with least squares to `(measured, reference)`
+    /// pairs. If all measured values are identical, only the offset is
+    /// fitted.
+    fn fit_linear(pairs: impl Iterator<Item = (i32, i32)>) -> Self {
+        let (mut n, mut sx, mut sy, mut sxx, mut sxy) = (0i128, 0i128, 0i128, 0i128, 0i128);
+        for (x, y) in pairs {
+            let (x, y) = (i128::from(x), i128::from(y));
+            n += 1;
+            sx += x;
+            sy += y;
+            sxx += x * x;
+            sxy += x * y;
+        }
+        let denominator = n * sxx - sx * sx;
+        let gain_ppm = if denominator > 0 {
+            i128::from(saturate(div_round(
+                (n * sxy - sx * sy) * 1_000_000,
+                denominator,
+            )))
+        } else {
+            1_000_000
+        };
+        let offset = div_round(sy * 1_000_000 - gain_ppm * sx, n.max(1) * 1_000_000);
+        Correction::Linear {
+            gain_ppm: gain_ppm as i32,
+            offset: saturate(offset),
+        }
+    }
+
+    /// Fit a piecewise-linear correction through `(measured, reference)`
+    /// pairs. The reference values of identical measured values are
+    /// averaged.
+    fn fit_piecewise(pairs: impl Iterator<Item = (i32, i32)>) -> Result<Self, FitError> {
+        // Measured value, sum of the reference values and count
+        let mut sums = [(0i32, 0i128, 0i128); MAX_BREAKPOINTS];
+        let mut len = 0;
+        for (x, y) in pairs {
+            match sums[..len].binary_search_by_key(&x, |sum| sum.0) {
+                Ok(i) => {
+                    sums[i].1 += i128::from(y);
+                    sums[i].2 += 1;
+                }
+                Err(_) if len == MAX_BREAKPOINTS => return Err(FitError::TooManyPoints),
+                Err(i) => {
+                    sums.copy_within(i..len, i + 1);
+                    sums[i] = (x, i128::from(y), 1);
+                    len += 1;
+                }
+            }
+        }
+        let mut points = [Breakpoint::default(); MAX_BREAKPOINTS];
+        for (point, (measured, sum, count)) in points.iter_mut().zip(&sums[..len]) {
+            *point = Breakpoint {
+                measured: *measured,
+                reference: saturate(div_round(*sum, *count)),
+            };
+        }
+        Breakpoints::new(&points[..len])
+            .map(Correction::Piecewise)
+            .ok_or(FitError::NoPoints)
+    }
+
+    fn write(&self, buf: &mut [u8]) {
+        match self {
+            Correction::Linear { gain_ppm, offset } => {
+                buf[0] = 0;
+                buf[2..6].copy_from_slice(&gain_ppm.to_le_bytes());
+                buf[6..10].copy_from_slice(&offset.to_le_bytes());
+            }
+            Correction::Piecewise(breakpoints) => {
+                buf[0] = 1;
+                buf[1] = breakpoints.len;
+                for (chunk, point) in buf[2..].chunks_exact_mut(8).zip(breakpoints.as_slice()) {
+                    chunk[..4].copy_from_slice(&point.measured.to_le_bytes());
+                    chunk[4..].copy_from_slice(&point.reference.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    fn read(buf: &[u8]) -> Result<Self, InvalidProfile> {
+        let i32_at = |i: usize| i32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        match buf[0] {
+            0 => Ok(Correction::Linear {
+                gain_ppm: i32_at(2),
+                offset: i32_at(6),
+            }),
+            1 if usize::from(buf[1]) <= MAX_BREAKPOINTS => {
+                let len = usize::from(buf[1]);
+                let mut points = [Breakpoint::default(); MAX_BREAKPOINTS];
+                for (i, point) in points[..len].iter_mut().enumerate() {
+                    *point = Breakpoint {
+                        measured: i32_at(2 + 8 * i),
+                        reference: i32_at(6 + 8 * i),
+                    };
+                }
+                Breakpoints::new(&points[..len])
+                    .map(Correction::Piecewise)
+                    .ok_or(InvalidProfile)
+            }
+            _ => Err(InvalidProfile),
+        }
+    }
+}
+
+/// A measurement of the sensor together with the reference values, e.g. of a
+/// reference hygrometer in a climate chamber.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CalibrationPoint {
+    /// The measurement of the sensor.
+    pub measurement: Measurement,
+    /// The reference temperature and humidity.
+    pub reference: Measurement,
+}
+
+/// Kind of corrections fitted by [`CalibrationProfile::fit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FitMethod {
+    /// Linear corrections (gain and offset), fitted with least squares.
+    Linear,
+    /// Piecewise-linear corrections through the calibration points (at most
+    /// [`MAX_BREAKPOINTS`] distinct measured values).
+    Piecewise,
+}
+
+/// Errors of [`CalibrationProfile::fit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FitError {
+    /// No calibration points were provided.
+    NoPoints,
+    /// More than [`MAX_BREAKPOINTS`] distinct measured values for a
+    /// piecewise-linear fit.
+    TooManyPoints,
+}
+
+/// A serialized [`CalibrationProfile`] is invalid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvalidProfile;
+
+/// Statistics of the differences between corrected measurements and the
+/// reference values.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Residual {
+    /// Mean difference (bias).
+    pub mean: i32,
+    /// Root mean square of the differences.
+    pub rms: u32,
+    /// Maximum absolute difference.
+    pub max: u32,
+}
+
+impl Residual {
+    fn new(differences: impl Iterator<Item = i32>) -> Self {
+        let (mut n, mut sum, mut sum_squares, mut max) = (0i128, 0i128, 0u128, 0);
+        for difference in differences {
+            n += 1;
+            sum += i128::from(difference);
+            sum_squares += u128::from(difference.unsigned_abs()).pow(2);
+            max = max.max(difference.unsigned_abs());
+        }
+        if n == 0 {
+            return Self::default();
+        }
+        Self {
+            mean: saturate(div_round(sum, n)),
+            rms: (sum_squares / n as u128).isqrt() as u32,
+            max,
+        }
+    }
+}
+
+/// Residuals of a [`CalibrationProfile`], in m°C and 1/1000 %RH.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Residuals {
+    /// Residual of the temperature.
+    pub temperature: Residual,
+    /// Residual of the humidity.
+    pub humidity: Residual,
+}
+
+/// Result of [`CalibrationProfile::fit`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fit {
+    /// The fitted profile.
+    pub profile: CalibrationProfile,
+    /// The residuals of the profile at the calibration points.
+    pub residuals: Residuals,
+}
+
+/// Temperature and humidity corrections of a sensor, fitted to measurements
+/// against a reference.
+///
+/// Like a [`Calibration`], the humidity correction is applied to the
+/// relative humidity at the measured temperature and the result is
+/// re-derived at the corrected temperature. A profile can be set on the
+/// driver with [`ShtCx::set_calibration`].
+///
+/// ```
+/// use shtcx::calibration::{CalibrationPoint, CalibrationProfile, FitMethod};
+/// use shtcx::{Humidity, Measurement, Temperature};
+///
+/// let m = |temperature, humidity| Measurement {
+///     temperature: Temperature::from_millidegrees_celsius(temperature),
+///     humidity: Humidity::from_millipercent(humidity),
+/// };
+/// let points = [
+///     CalibrationPoint { measurement: m(10_400, 30_800), reference: m(10_000, 30_000) },
+///     CalibrationPoint { measurement: m(25_500, 51_500), reference: m(25_000, 50_000) },
+///     CalibrationPoint { measurement: m(40_600, 72_200), reference: m(40_000, 70_000) },
+/// ];
+/// let fit = CalibrationProfile::fit(&points, FitMethod::Piecewise).unwrap();
+/// assert_eq!(fit.residuals.temperature.max, 0);
+///
+/// // Store the profile
+/// let bytes = fit.profile.to_bytes();
+/// let profile = CalibrationProfile::from_bytes(&bytes).unwrap();
+/// let corrected = profile.apply(m(25_500, 51_500));
+/// assert_eq!(corrected.temperature.as_millidegrees_celsius(), 25_000);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CalibrationProfile {
+    /// Correction of the temperature.
+    pub temperature: Correction,
+    /// Correction of the humidity (at the measured temperature).
+    pub humidity: Correction,
+}
+
+impl CalibrationProfile {
+    /// The identity profile that doesn't change measurements.
+    pub const NONE: CalibrationProfile = CalibrationProfile {
+        temperature: Correction::NONE,
+        humidity: Correction::NONE,
+    };
+
+    /// Length of a serialized profile in bytes.
+    pub const SERIALIZED_LEN: usize = 2 * CORRECTION_LEN;
+
+    /// Fit a profile to calibration points.
+    ///
+    /// The temperature and humidity corrections are fitted independently.
+    /// The reference humidity is converted to the measured temperature before
+    /// fitting the humidity correction, so sensor and reference don't need
+    /// to have exactly the same temperature. For piecewise-linear fits,
+    /// repeated readings at the same set point should be averaged first (e.g.
+    /// with [`ShtCx::measure_averaged`]).
+    pub fn fit(points: &[CalibrationPoint], method: FitMethod) -> Result<Fit, FitError> {
+        if points.is_empty() {
+            return Err(FitError::NoPoints);
+        }
+        let temperatures = points.iter().map(|point| {
+            (
+                point.measurement.temperature.as_millidegrees_celsius(),
+                point.reference.temperature.as_millidegrees_celsius(),
+            )
+        });
+        let humidities = points.iter().map(|point| {
+            (
+                point.measurement.humidity.as_millipercent(),
+                rederive(
+                    point.reference.humidity.as_millipercent(),
+                    point.reference.temperature,
+                    point.measurement.temperature,
+                ),
+            )
+        });
+        let profile = match method {
+            FitMethod::Linear => CalibrationProfile {
+                temperature: Correction::fit_linear(temperatures),
+                humidity: Correction::fit_linear(humidities),
+            },
+            FitMethod::Piecewise => CalibrationProfile {
+                temperature: Correction::fit_piecewise(temperatures)?,
+                humidity: Correction::fit_piecewise(humidities)?,
+            },
+        };
+        Ok(Fit {
+            profile,
+            residuals: profile.residuals(points),
+        })
+    }
+
+    /// Correct a measurement.
+    ///
+    /// The humidity correction is applied first. Then the humidity is
+    /// converted from the measured to the corrected temperature and clamped
+    /// to 0..100 %RH.
+    pub fn apply(&self, measurement: Measurement) -> Measurement {
+        let temperature = self.apply_temperature(measurement.temperature);
+        let humidity = rederive(
+            self.apply_humidity(measurement.humidity).as_millipercent(),
+            measurement.temperature,
+            temperature,
+        );
+        Measurement {
+            temperature,
+            humidity: Humidity::from_millipercent(humidity.clamp(0, 100_000)),
+        }
+    }
+
+    /// Correct a temperature.
+    pub fn apply_temperature(&self, temperature: Temperature) -> Temperature {
+        Temperature::from_millidegrees_celsius(
+            self.temperature
+                .apply(temperature.as_millidegrees_celsius()),
+        )
+    }
+
+    /// Correct a humidity, clamped to 0..100 %RH.
+    ///
+    /// This doesn't re-derive the humidity at the corrected temperature,
+    /// which needs the measured temperature. If the profile
+    /// [corrects the temperature](CalibrationProfile::corrects_temperature),
+    /// use [`apply`](CalibrationProfile::apply) with a combined measurement
+    /// instead.
+    pub fn apply_humidity(&self, humidity: Humidity) -> Humidity {
+        Humidity::from_millipercent(
+            self.humidity
+                .apply(humidity.as_millipercent())
+                .clamp(0, 100_000),
+        )
+    }
+
+    /// Return whether the profile changes the temperature (and therefore the
+    /// relative humidity).
+    pub fn corrects_temperature(&self) -> bool {
+        self.temperature != Correction::NONE
+    }
+
+    /// Return the residuals (corrected measurement minus reference) of the
+    /// profile at the given calibration points.
+    pub fn residuals(&self, points: &[CalibrationPoint]) -> Residuals {
+        let corrected = |point: &CalibrationPoint| (self.apply(point.measurement), point.reference);
+        Residuals {
+            temperature: Residual::new(points.iter().map(corrected).map(|(m, reference)| {
+                m.temperature.as_millidegrees_celsius()
+                    - reference.temperature.as_millidegrees_celsius()
+            })),
+            humidity: Residual::new(points.iter().map(corrected).map(|(m, reference)| {
+                m.humidity.as_millipercent() - reference.humidity.as_millipercent()
+            })),
+        }
+    }
+
+    /// Serialize the profile (little endian).
+    pub fn to_bytes(&self) -> [u8; Self::SERIALIZED_LEN] {
+        let mut bytes = [0; Self::SERIALIZED_LEN];
+        let (temperature, humidity) = bytes.split_at_mut(CORRECTION_LEN);
+        self.temperature.write(temperature);
+        self.humidity.write(humidity);
+        bytes
+    }
+
+    /// Deserialize a profile created with
+    /// [`to_bytes`](CalibrationProfile::to_bytes).
+    pub fn from_bytes(bytes: &[u8; Self::SERIALIZED_LEN]) -> Result<Self, InvalidProfile> {
+        let (temperature, humidity) = bytes.split_at(CORRECTION_LEN);
+        Ok(Self {
+            temperature: Correction::read(temperature)?,
+            humidity: Correction::read(humidity)?,
+        })
+    }
+}
+
+impl Default for CalibrationProfile {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// A static calibration is a profile with linear corrections.
+impl From<Calibration> for CalibrationProfile {
+    fn from(calibration: Calibration) -> Self {
+        Self {
+            temperature: Correction::Linear {
+                gain_ppm: 1_000_000,
+                offset: calibration.temperature_offset,
+            },
+            humidity: Correction::Linear {
+                gain_ppm: calibration.humidity_gain_ppm,
+                offset: calibration.humidity_offset,
+            },
+        }
+    }
+}
+
+/// Calibration.
+impl<S: ShtSensor, I2C> ShtCx<S, I2C> {
+    /// Return the calibration applied to measurements.
+    ///
+    /// A [`Calibration`] that was set is returned as the equivalent
+    /// profile.
+    pub fn calibration(&self) -> &CalibrationProfile {
+        &self.calibration
+    }
+
+    /// Set the calibration applied to all measurements returned by the
+    /// driver.
+    ///
+    /// This accepts a static [`Calibration`] or a [`CalibrationProfile`]
+    /// (e.g. from [`CalibrationProfile::fit`]).
+    ///
+    /// Temperature-only measurements are corrected with
+    /// [`apply_temperature`](CalibrationProfile::apply_temperature). For
+    /// humidity-only measurements, the driver reads the temperature as well
+    /// if the temperature is corrected, so the humidity is the same as the
+    /// one of a combined measurement. Raw measurements are not modified.
+    pub fn set_calibration(&mut self, calibration: impl Into<CalibrationProfile>) {
+        self.calibration = calibration.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, MeasurementOrder, PowerMode, RawMeasurement};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    #[test]
+    fn none() {
+        for measurement in [m(-40_000, 0), m(23_730, 62_968), m(125_000, 100_000)] {
+            assert_eq!(Calibration::NONE.apply(measurement), measurement);
+        }
+        assert_eq!(Calibration::default(), Calibration::NONE);
+    }
+
+    #[test]
+    fn humidity_gain_offset() {
+        let calibration = Calibration {
+            humidity_offset: -1_000,
+            humidity_gain_ppm: 1_020_000,
+            ..Calibration::NONE
+        };
+        assert_eq!(calibration.apply(m(20_000, 50_000)), m(20_000, 50_000));
+        assert_eq!(calibration.apply(m(20_000, 40_000)), m(20_000, 39_800));
+        // Clamped
+        assert_eq!(calibration.apply(m(20_000, 0)), m(20_000, 0));
+        assert_eq!(calibration.apply(m(20_000, 99_500)), m(20_000, 100_000));
+    }
+
+    /// The absolute humidity doesn't change when the temperature is
+    /// compensated.
+    #[test]
+    fn temperature_offset() {
+        let calibration = Calibration {
+            temperature_offset: -2_000,
+            ..Calibration::NONE
+        };
+        let corrected = calibration.apply(m(25_000, 50_000));
+        assert_eq!(corrected.temperature.as_millidegrees_celsius(), 23_000);
+        // E(25 °C) / E(23 °C) = 31.67 hPa / 28.10 hPa
+        assert!((corrected.humidity.as_millipercent() - 56_350).abs() < 50);
+        // Same dew point
+        assert!(
+            (corrected.dew_point().as_millidegrees_celsius()
+                - m(25_000, 50_000).dew_point().as_millidegrees_celsius())
+            .abs()
+                < 10
+        );
+
+        // Condensation: Clamped to 100 %RH
+        assert_eq!(calibration.apply(m(25_000, 95_000)), m(23_000, 100_000));
+
+        // The sensor reads too low: The relative humidity of the warmer air
+        // is lower
+        let calibration = Calibration {
+            temperature_offset: 1_500,
+            ..Calibration::NONE
+        };
+        assert_eq!(
+            calibration.apply_temperature(Temperature::from_millidegrees_celsius(20_000)),
+            Temperature::from_millidegrees_celsius(21_500)
+        );
+        assert!(
+            calibration
+                .apply(m(20_000, 50_000))
+                .humidity
+                .as_millipercent()
+                < 50_000
+        );
+    }
+
+    #[test]
+    fn driver() {
+        let response = vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c];
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, response.clone()),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, response.clone()),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, response.clone()),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, response[..3].to_vec()),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        assert_eq!(sht.calibration(), &CalibrationProfile::NONE);
+        let calibration = Calibration {
+            temperature_offset: -730,
+            humidity_offset: 2_000,
+            ..Calibration::NONE
+        };
+        sht.set_calibration(calibration);
+
+        let measurement = sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        assert_eq!(measurement, calibration.apply(m(23_730, 62_968)));
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_000);
+
+        let pending = sht.start_measurement(PowerMode::LowPower, 0).unwrap();
+        assert_eq!(pending.finish(&mut sht).unwrap(), measurement);
+
+        // Raw measurements are not modified
+        sht.start_measure_partial(PowerMode::LowPower, MeasurementOrder::TemperatureFirst)
+            .unwrap();
+        assert_eq!(
+            sht.get_raw_measurement_result().unwrap(),
+            RawMeasurement {
+                temperature: 0x648b,
+                humidity: 0xa133,
+            }
+        );
+
+        let temperature = sht
+            .measure_temperature(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(temperature.as_millidegrees_celsius(), 23_000);
+        sht.destroy().done();
+    }
+
+    /// Humidity-only measurements are re-derived at the compensated
+    /// temperature, like combined measurements.
+    #[test]
+    fn driver_humidity() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            // Humidity first: Both words are read
+            Transaction::write(SHT_ADDR, vec![0x40, 0x1a]),
+            Transaction::read(SHT_ADDR, vec![0xa1, 0x33, 0x1c, 0x64, 0x8b, 0xc7]),
+            Transaction::write(SHT_ADDR, vec![0x40, 0x1a]),
+            Transaction::read(SHT_ADDR, vec![0xa1, 0x33, 0x1c, 0x64, 0x8b, 0xc7]),
+            // Without temperature offset, only the humidity is read
+            Transaction::write(SHT_ADDR, vec![0x40, 0x1a]),
+            Transaction::read(SHT_ADDR, vec![0xa1, 0x33, 0x1c]),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let calibration = Calibration {
+            temperature_offset: -2_000,
+            ..Calibration::NONE
+        };
+        sht.set_calibration(calibration);
+
+        let measurement = sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        assert!(measurement.humidity.as_millipercent() > 62_968);
+        let humidity = sht
+            .measure_humidity(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(humidity, measurement.humidity);
+        let pending = sht
+            .start_humidity_measurement(PowerMode::LowPower, 0)
+            .unwrap();
+        assert_eq!(pending.finish(&mut sht).unwrap(), measurement.humidity);
+
+        let calibration = Calibration {
+            humidity_offset: 1_000,
+            ..Calibration::NONE
+        };
+        sht.set_calibration(calibration);
+        let humidity = sht
+            .measure_humidity(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(humidity.as_millipercent(), 63_968);
+        sht.destroy().done();
+    }
+
+    fn point(measurement: Measurement, reference: Measurement) -> CalibrationPoint {
+        CalibrationPoint {
+            measurement,
+            reference,
+        }
+    }
+
+    #[test]
+    fn fit_errors() {
+        assert_eq!(
+            CalibrationProfile::fit(&[], FitMethod::Linear),
+            Err(FitError::NoPoints)
+        );
+        let points: Vec<_> = (0..=MAX_BREAKPOINTS as i32)
+            .map(|i| point(m(i * 1_000, 50_000), m(i * 1_000, 50_000)))
+            .collect();
+        assert_eq!(
+            CalibrationProfile::fit(&points, FitMethod::Piecewise),
+            Err(FitError::TooManyPoints)
+        );
+        assert!(CalibrationProfile::fit(&points, FitMethod::Linear).is_ok());
+        // Duplicates are merged
+        assert!(CalibrationProfile::fit(&points[1..], FitMethod::Piecewise).is_ok());
+    }
+
+    #[test]
+    fn fit_linear() {
+        // Reference humidity = 0.98 * measured + 1 %RH
+        let points: Vec<_> = [20_000, 40_000, 60_000, 80_000]
+            .into_iter()
+            .map(|h| point(m(25_000, h), m(25_000, h * 98 / 100 + 1_000)))
+            .collect();
+        let fit = CalibrationProfile::fit(&points, FitMethod::Linear).unwrap();
+        assert_eq!(fit.profile.temperature, Correction::NONE);
+        assert_eq!(
+            fit.profile.humidity,
+            Correction::Linear {
+                gain_ppm: 980_000,
+                offset: 1_000,
+            }
+        );
+        assert_eq!(fit.residuals, Residuals::default());
+        assert_eq!(fit.profile.apply(m(25_000, 50_000)), m(25_000, 50_000));
+
+        // A single point only fits the offset
+        let fit = CalibrationProfile::fit(&points[..1], FitMethod::Linear).unwrap();
+        assert_eq!(
+            fit.profile.humidity,
+            Correction::Linear {
+                gain_ppm: 1_000_000,
+                offset: 600,
+            }
+        );
+    }
+
+    /// The sensor reads 2 °C too high, but measures the absolute humidity
+    /// correctly.
+    #[test]
+    fn fit_temperature_offset() {
+        let calibration = Calibration {
+            temperature_offset: -2_000,
+            ..Calibration::NONE
+        };
+        let points: Vec<_> = [(12_000, 30_000), (22_000, 50_000), (32_000, 70_000)]
+            .into_iter()
+            .map(|(t, h)| point(m(t, h), calibration.apply(m(t, h))))
+            .collect();
+        let fit = CalibrationProfile::fit(&points, FitMethod::Linear).unwrap();
+        assert_eq!(
+            fit.profile.temperature,
+            Correction::Linear {
+                gain_ppm: 1_000_000,
+                offset: -2_000,
+            }
+        );
+        let Correction::Linear { gain_ppm, offset } = fit.profile.humidity else {
+            panic!("Not linear");
+        };
+        assert!(gain_ppm.abs_diff(1_000_000) < 1_000);
+        assert!(offset.abs() < 100);
+        assert!(fit.residuals.temperature.max == 0);
+        assert!(fit.residuals.humidity.max < 20);
+    }
+
+    #[test]
+    fn fit_piecewise() {
+        let points = [
+            point(m(0, 50_000), m(500, 50_000)),
+            point(m(20_000, 50_000), m(20_000, 50_000)),
+            // Averaged
+            point(m(40_000, 50_000), m(38_000, 50_000)),
+            point(m(40_000, 50_000), m(40_000, 50_000)),
+        ];
+        let fit = CalibrationProfile::fit(&points, FitMethod::Piecewise).unwrap();
+        let Correction::Piecewise(breakpoints) = fit.profile.temperature else {
+            panic!("Not piecewise");
+        };
+        assert_eq!(
+            breakpoints.as_slice(),
+            [
+                Breakpoint {
+                    measured: 0,
+                    reference: 500
+                },
+                Breakpoint {
+                    measured: 20_000,
+                    reference: 20_000
+                },
+                Breakpoint {
+                    measured: 40_000,
+                    reference: 39_000
+                },
+            ]
+        );
+        let correction = fit.profile.temperature;
+        assert_eq!(correction.apply(10_000), 10_250);
+        assert_eq!(correction.apply(30_000), 29_500);
+        // Outside of the breakpoints, the offset of the nearest one is used
+        assert_eq!(correction.apply(-10_000), -9_500);
+        assert_eq!(correction.apply(50_000), 49_000);
+
+        assert_eq!(
+            fit.residuals.temperature,
+            Residual {
+                mean: 0,
+                rms: 707,
+                max: 1_000,
+            }
+        );
+    }
+
+    /// A static calibration is applied exactly like the equivalent profile.
+    #[test]
+    fn calibration_as_profile() {
+        let calibration = Calibration {
+            temperature_offset: -1_200,
+            humidity_offset: 700,
+            humidity_gain_ppm: 1_030_000,
+        };
+        let profile = CalibrationProfile::from(calibration);
+        assert!(profile.corrects_temperature());
+        assert!(!CalibrationProfile::from(Calibration::NONE).corrects_temperature());
+        assert_eq!(
+            CalibrationProfile::from(Calibration::NONE),
+            CalibrationProfile::NONE
+        );
+        for measurement in [m(-40_000, 0), m(23_730, 62_968), m(60_000, 98_000)] {
+            assert_eq!(calibration.apply(measurement), profile.apply(measurement));
+        }
+    }
+
+    /// A fitted profile is applied by the driver.
+    #[test]
+    fn driver_profile() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            Transaction::write(SHT_ADDR, vec![0x40, 0x1a]),
+            Transaction::read(SHT_ADDR, vec![0xa1, 0x33, 0x1c, 0x64, 0x8b, 0xc7]),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+
+        // The sensor reads 0.73 °C too high and 2 % too much humidity
+        let points: Vec<_> = [30_600, 51_000, 71_400]
+            .into_iter()
+            .map(|h| point(m(23_730, h), m(23_000, h * 100 / 102)))
+            .collect();
+        let fit = CalibrationProfile::fit(&points, FitMethod::Linear).unwrap();
+        assert_eq!(
+            fit.profile.temperature,
+            Correction::Linear {
+                gain_ppm: 1_000_000,
+                offset: -730,
+            }
+        );
+        sht.set_calibration(fit.profile);
+        assert_eq!(sht.calibration(), &fit.profile);
+
+        let measurement = sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        assert_eq!(measurement, fit.profile.apply(m(23_730, 62_968)));
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_000);
+        // The reference humidity at 23 °C: 62.968 %RH / 1.02
+        assert!(measurement.humidity.as_millipercent().abs_diff(61_733) < 20);
+
+        let humidity = sht
+            .measure_humidity(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(humidity, measurement.humidity);
+        sht.destroy().done();
+    }
+
+    #[test]
+    fn breakpoints() {
+        let breakpoint = |measured| Breakpoint {
+            measured,
+            reference: 0,
+        };
+        assert!(Breakpoints::new(&[]).is_none());
+        assert!(Breakpoints::new(&[breakpoint(1), breakpoint(1)]).is_none());
+        assert!(Breakpoints::new(&[breakpoint(2), breakpoint(1)]).is_none());
+        assert!(Breakpoints::new(&[breakpoint(0); MAX_BREAKPOINTS + 1]).is_none());
+        let breakpoints = Breakpoints::new(&[breakpoint(1), breakpoint(2)]).unwrap();
+        assert_eq!(breakpoints.as_slice(), [breakpoint(1), breakpoint(2)]);
+    }
+
+    #[test]
+    fn serialization() {
+        let linear = CalibrationProfile {
+            temperature: Correction::Linear {
+                gain_ppm: 1_010_000,
+                offset: -2_000,
+            },
+            humidity: Correction::NONE,
+        };
+        let breakpoints = Breakpoints::new(&[
+            Breakpoint {
+                measured: -1,
+                reference: i32::MIN,
+            },
+            Breakpoint {
+                measured: 100_000,
+                reference: i32::MAX,
+            },
+        ])
+        .unwrap();
+        let piecewise = CalibrationProfile {
+            temperature: Correction::Piecewise(breakpoints),
+            humidity: Correction::Piecewise(breakpoints),
+        };
+        for profile in [CalibrationProfile::NONE, linear, piecewise] {
+            let bytes = profile.to_bytes();
+            assert_eq!(CalibrationProfile::from_bytes(&bytes), Ok(profile));
+        }
+
+        let mut bytes = linear.to_bytes();
+        assert_eq!(
+            &bytes[..10],
+            [0, 0, 0x50, 0x69, 0x0f, 0, 0x30, 0xf8, 0xff, 0xff]
+        );
+        bytes[0] = 2;
+        assert_eq!(CalibrationProfile::from_bytes(&bytes), Err(InvalidProfile));
+        // Too many breakpoints
+        let mut bytes = piecewise.to_bytes();
+        bytes[1] = MAX_BREAKPOINTS as u8 + 1;
+        assert_eq!(CalibrationProfile::from_bytes(&bytes), Err(InvalidProfile));
+        // Unsorted breakpoints
+        bytes[1] = 3;
+        assert_eq!(CalibrationProfile::from_bytes(&bytes), Err(InvalidProfile));
+    }
+}
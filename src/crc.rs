@@ -17,6 +17,24 @@ pub(crate) fn crc8(data: &[u8]) -> u8 {
     crc
 }
 
+/// Calculate the CRC-32 (IEEE 802.3) checksum.
+#[cfg(any(test, feature = "embedded-storage"))]
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+    let mut crc: u32 = 0xffff_ffff;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            if (crc & 1) > 0 {
+                crc = (crc >> 1) ^ CRC32_POLYNOMIAL;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    !crc
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +46,10 @@ mod tests {
         assert_eq!(crc8(&[0x00]), 0xac);
         assert_eq!(crc8(&[0xbe, 0xef]), 0x92);
     }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
 }
@@ -0,0 +1,317 @@
+//! Estimation of the energy consumption of the sensor.
+//!
+//! Based on the supply currents from the datasheets and the maximum
+//! measurement duration (see [`MeasurementDuration`]), the average current and
+//! the energy per measurement can be estimated for a given sensor class, power
+//! mode, sampling period and sleep strategy.
+//!
+//! ```
+//! use shtcx::{energy::{self, SleepStrategy}, sensor_class::Sht2Gen, PowerMode};
+//!
+//! // SHTC3, one low power measurement every 10 s, sleep in between, 3.3 V
+//! let estimate =
+//!     energy::estimate::<Sht2Gen>(PowerMode::LowPower, 10_000, SleepStrategy::Sleep, 3_300)
+//!         .unwrap();
+//! println!("Average current: {} nA", estimate.average_current_na);
+//! println!("Battery life (CR2032): {} h", estimate.battery_life_hours(220));
+//! ```
+//!
+//! Note: These are estimations based on typical datasheet figures. Leakage
+//! currents, pull-up resistors and the I²C communication itself are not taken
+//! into account.
+
+use crate::{sensor_class, MeasurementDuration, PowerMode};
+
+/// Time the sensor needs until it is ready after a wakeup call (datasheet
+/// table 5).
+const WAKEUP_TIME_US: u32 = 240;
+
+/// Time the sensor needs until it is ready after power-up.
+const POWER_UP_TIME_US: u32 = 1_000;
+
+/// Typical supply currents of a sensor, in nA.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SupplyCurrents {
+    /// Supply current while measuring.
+    pub measurement_na: u32,
+    /// Supply current in idle state.
+    pub idle_na: u32,
+    /// Supply current in sleep mode (`None` if sleep mode isn't supported).
+    pub sleep_na: Option<u32>,
+}
+
+/// Supply currents of a sensor class.
+pub trait SupplyCurrent: MeasurementDuration {
+    /// Typical supply currents according to the datasheet.
+    const SUPPLY_CURRENTS: SupplyCurrents;
+}
+
+impl SupplyCurrent for sensor_class::Sht1Gen {
+    /// SHTC1/SHTW2 datasheet: Measuring 385 µA, idle 1 µA, no sleep mode.
+    const SUPPLY_CURRENTS: SupplyCurrents = SupplyCurrents {
+        measurement_na: 385_000,
+        idle_na: 1_000,
+        sleep_na: None,
+    };
+}
+
+impl SupplyCurrent for sensor_class::Sht2Gen {
+    /// SHTC3 datasheet: Measuring 430 µA, idle 45 µA, sleep 0.3 µA.
+    const SUPPLY_CURRENTS: SupplyCurrents = SupplyCurrents {
+        measurement_na: 430_000,
+        idle_na: 45_000,
+        sleep_na: Some(300),
+    };
+}
+
+impl SupplyCurrent for sensor_class::ShtGeneric {
+    /// Because these currents should cover all sensor models, the maximum of
+    /// all models is chosen.
+    const SUPPLY_CURRENTS: SupplyCurrents = SupplyCurrents {
+        measurement_na: 430_000,
+        idle_na: 45_000,
+        sleep_na: Some(300),
+    };
+}
+
+/// What the sensor does between measurements.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SleepStrategy {
+    /// The sensor stays in idle state.
+    Idle,
+    /// The sensor is put to sleep and woken up before every measurement.
+    Sleep,
+    /// The sensor is powered off and powered up before every measurement.
+    PowerGated,
+}
+
+/// Error returned by [`estimate`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EstimateError {
+    /// The sensor does not support sleep mode.
+    SleepNotSupported,
+    /// The sampling period is zero or shorter than a single measurement
+    /// cycle.
+    PeriodTooShort,
+    /// The energy doesn't fit into a `u64` (only possible with unrealistic
+    /// supply currents or voltages).
+    Overflow,
+}
+
+/// Estimated energy consumption.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EnergyEstimate {
+    /// Average supply current in nA.
+    pub average_current_na: u32,
+    /// Energy needed for a single measurement (including wakeup or power-up)
+    /// in nJ.
+    pub energy_per_measurement_nj: u64,
+    /// Energy needed per sampling period (including idle or sleep time) in
+    /// nJ.
+    pub energy_per_period_nj: u64,
+}
+
+impl EnergyEstimate {
+    /// Return the estimated battery life in hours for a battery with the
+    /// given capacity in mAh.
+    pub fn battery_life_hours(&self, capacity_mah: u32) -> u32 {
+        if self.average_current_na == 0 {
+            return u32::MAX;
+        }
+        let hours = u64::from(capacity_mah) * 1_000_000 / u64::from(self.average_current_na);
+        hours.min(u64::from(u32::MAX)) as u32
+    }
+}
+
+/// Estimate the energy consumption of sensor class `S` with the typical
+/// supply currents from the datasheet.
+///
+/// One measurement in power mode `mode` is done every `sampling_period_ms`
+/// milliseconds, with a supply voltage of `supply_mv` millivolts.
+pub fn estimate<S: SupplyCurrent>(
+    mode: PowerMode,
+    sampling_period_ms: u32,
+    strategy: SleepStrategy,
+    supply_mv: u32,
+) -> Result<EnergyEstimate, EstimateError> {
+    estimate_with_currents(
+        S::SUPPLY_CURRENTS,
+        S::max_measurement_duration(mode),
+        sampling_period_ms,
+        strategy,
+        supply_mv,
+    )
+}
+
+/// Estimate the energy consumption with custom supply currents and
+/// measurement duration (in µs).
+///
+/// See [`estimate`] for details.
+pub fn estimate_with_currents(
+    currents: SupplyCurrents,
+    measurement_duration_us: u32,
+    sampling_period_ms: u32,
+    strategy: SleepStrategy,
+    supply_mv: u32,
+) -> Result<EnergyEstimate, EstimateError> {
+    // Overhead before every measurement (time in µs, spent in idle state)
+    // and current between measurements.
+    let (overhead_us, rest_na) = match strategy {
+        SleepStrategy::Idle => (0, currents.idle_na),
+        SleepStrategy::Sleep => (
+            WAKEUP_TIME_US,
+            currents.sleep_na.ok_or(EstimateError::SleepNotSupported)?,
+        ),
+        SleepStrategy::PowerGated => (POWER_UP_TIME_US, 0),
+    };
+    let period_us = u128::from(sampling_period_ms) * 1000;
+    let active_us = u128::from(measurement_duration_us) + u128::from(overhead_us);
+    if period_us == 0 {
+        return Err(EstimateError::PeriodTooShort);
+    }
+    let rest_us = period_us
+        .checked_sub(active_us)
+        .ok_or(EstimateError::PeriodTooShort)?;
+
+    // Charge in nA·µs (1e-15 C). With long sampling periods, this doesn't
+    // fit into a u64.
+    let active_charge = u128::from(currents.measurement_na) * u128::from(measurement_duration_us)
+        + u128::from(currents.idle_na) * u128::from(overhead_us);
+    let period_charge = active_charge + u128::from(rest_na) * rest_us;
+
+    // nA·µs · mV = 1e-18 J
+    let to_nj = |charge: u128| {
+        u64::try_from(charge * u128::from(supply_mv) / 1_000_000_000)
+            .map_err(|_| EstimateError::Overflow)
+    };
+    Ok(EnergyEstimate {
+        // The average is never larger than the largest supply current, so it
+        // always fits into a u32.
+        average_current_na: (period_charge / period_us) as u32,
+        energy_per_measurement_nj: to_nj(active_charge)?,
+        energy_per_period_nj: to_nj(period_charge)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sensor_class::{Sht1Gen, Sht2Gen};
+
+    #[test]
+    fn shtc3_sleep() {
+        let estimate =
+            estimate::<Sht2Gen>(PowerMode::NormalMode, 1_000, SleepStrategy::Sleep, 3_300).unwrap();
+        // 430 µA · 12.1 ms + 45 µA · 0.24 ms + 0.3 µA · 987.66 ms per second
+        assert_eq!(estimate.average_current_na, 5_510);
+        // (5203 + 10.8) nC · 3.3 V
+        assert_eq!(estimate.energy_per_measurement_nj, 17_205);
+        assert_eq!(estimate.energy_per_period_nj, 18_183);
+    }
+
+    #[test]
+    fn shtc3_low_power_is_cheaper() {
+        let normal =
+            estimate::<Sht2Gen>(PowerMode::NormalMode, 1_000, SleepStrategy::Sleep, 3_300).unwrap();
+        let low_power =
+            estimate::<Sht2Gen>(PowerMode::LowPower, 1_000, SleepStrategy::Sleep, 3_300).unwrap();
+        assert!(low_power.average_current_na < normal.average_current_na);
+        assert!(low_power.energy_per_measurement_nj < normal.energy_per_measurement_nj);
+    }
+
+    #[test]
+    fn shtc3_idle() {
+        let estimate =
+            estimate::<Sht2Gen>(PowerMode::LowPower, 1_000, SleepStrategy::Idle, 3_300).unwrap();
+        // 430 µA · 0.8 ms + 45 µA · 999.2 ms per second
+        assert_eq!(estimate.average_current_na, 45_308);
+    }
+
+    #[test]
+    fn shtc1() {
+        assert_eq!(
+            estimate::<Sht1Gen>(PowerMode::NormalMode, 1_000, SleepStrategy::Sleep, 3_300),
+            Err(EstimateError::SleepNotSupported)
+        );
+        let estimate = estimate::<Sht1Gen>(
+            PowerMode::NormalMode,
+            1_000,
+            SleepStrategy::PowerGated,
+            3_300,
+        )
+        .unwrap();
+        // 385 µA · 14.4 ms + 1 µA · 1 ms per second
+        assert_eq!(estimate.average_current_na, 5_545);
+    }
+
+    #[test]
+    fn period_too_short() {
+        assert_eq!(
+            estimate::<Sht1Gen>(PowerMode::NormalMode, 14, SleepStrategy::Idle, 3_300),
+            Err(EstimateError::PeriodTooShort)
+        );
+        assert!(estimate::<Sht1Gen>(PowerMode::NormalMode, 15, SleepStrategy::Idle, 3_300).is_ok());
+        // Zero period with zero measurement duration
+        assert_eq!(
+            estimate_with_currents(Sht2Gen::SUPPLY_CURRENTS, 0, 0, SleepStrategy::Idle, 3_300),
+            Err(EstimateError::PeriodTooShort)
+        );
+    }
+
+    #[test]
+    fn long_period() {
+        let idle = |sampling_period_ms, supply_mv| {
+            estimate::<Sht2Gen>(
+                PowerMode::NormalMode,
+                sampling_period_ms,
+                SleepStrategy::Idle,
+                supply_mv,
+            )
+            .unwrap()
+        };
+
+        // One measurement every 3 days: 45 µA · 3.3 V · 259200 s = 38.5 J
+        let estimate = idle(3 * 24 * 60 * 60 * 1000, 3_300);
+        assert_eq!(estimate.average_current_na, 45_000);
+        assert_eq!(estimate.energy_per_measurement_nj, 17_169);
+        assert_eq!(estimate.energy_per_period_nj, 38_491_215_373);
+
+        // 8 h and 9 h: 45 µA · 3.3 V · 28800 s = 4.277 J and 4.811 J
+        let estimate = idle(8 * 60 * 60 * 1000, 3_300);
+        assert_eq!(estimate.energy_per_period_nj, 4_276_815_373);
+        let estimate = idle(9 * 60 * 60 * 1000, 3_300);
+        assert_eq!(estimate.energy_per_period_nj, 4_811_415_373);
+
+        let estimate = idle(u32::MAX, u32::MAX);
+        assert_eq!(estimate.average_current_na, 45_000);
+        assert_eq!(estimate.energy_per_measurement_nj, 22_346_714_835);
+        assert_eq!(estimate.energy_per_period_nj, 830_103_502_938_487_909);
+
+        let currents = SupplyCurrents {
+            measurement_na: u32::MAX,
+            idle_na: u32::MAX,
+            sleep_na: None,
+        };
+        assert_eq!(
+            estimate_with_currents(currents, 0, u32::MAX, SleepStrategy::Idle, u32::MAX),
+            Err(EstimateError::Overflow)
+        );
+    }
+
+    #[test]
+    fn battery_life() {
+        let estimate = EnergyEstimate {
+            average_current_na: 5_000,
+            energy_per_measurement_nj: 0,
+            energy_per_period_nj: 0,
+        };
+        // 220 mAh / 5 µA
+        assert_eq!(estimate.battery_life_hours(220), 44_000);
+        let estimate = EnergyEstimate {
+            average_current_na: 0,
+            ..estimate
+        };
+        assert_eq!(estimate.battery_life_hours(220), u32::MAX);
+    }
+}
@@ -0,0 +1,274 @@
+//! Tracking of the exposure to high humidity.
+//!
+//! Prolonged exposure to high humidity causes a temporary offset of the
+//! humidity reading (creep), which slowly disappears under normal conditions
+//! (see the "Normal Operating Conditions" section of the datasheets). The
+//! [`ExposureTracker`] accumulates the time the humidity spends above
+//! configurable [levels](ExposureLevel). Readings taken while the humidity
+//! is above a level, or after the allowed exposure time of a level was
+//! exceeded, are flagged as lower confidence. Once the humidity stayed below
+//! the recovery humidity for the recovery time, the accumulated exposure is
+//! cleared and the recovery is reported.
+//!
+//! ```
+//! use shtcx::exposure::{ExposureConfig, ExposureLevel, ExposureTracker};
+//! use shtcx::Humidity;
+//!
+//! const HOUR_MS: u32 = 60 * 60 * 1000;
+//! let mut tracker = ExposureTracker::new(ExposureConfig {
+//!     levels: [
+//!         ExposureLevel { humidity: 80_000, max_exposure_ms: 60 * HOUR_MS },
+//!         ExposureLevel { humidity: 90_000, max_exposure_ms: 10 * HOUR_MS },
+//!     ],
+//!     recovery_humidity: 60_000,
+//!     recovery_ms: 24 * HOUR_MS,
+//! });
+//! let report = tracker.update(Humidity::from_millipercent(92_000), 0);
+//! assert!(report.exposed);
+//! assert!(report.is_low_confidence());
+//! ```
+
+use crate::Humidity;
+
+/// A humidity level with an allowed exposure time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExposureLevel {
+    /// Humidity in 1/1000 %RH above which the exposure time accumulates.
+    pub humidity: i32,
+    /// Cumulative time in milliseconds above `humidity` after which creep of
+    /// the humidity reading is likely.
+    pub max_exposure_ms: u32,
+}
+
+/// Configuration of an [`ExposureTracker`] with `N` levels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExposureConfig<const N: usize> {
+    /// The exposure levels.
+    pub levels: [ExposureLevel; N],
+    /// Humidity in 1/1000 %RH at or below which the sensor recovers.
+    pub recovery_humidity: i32,
+    /// Time in milliseconds the humidity must stay at or below
+    /// `recovery_humidity` for the sensor to be considered recovered.
+    pub recovery_ms: u32,
+}
+
+/// Exposure state at the time of a reading.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ExposureReport {
+    /// The humidity is above at least one level.
+    pub exposed: bool,
+    /// The allowed exposure time of at least one level was exceeded and the
+    /// sensor hasn't recovered yet.
+    pub creep: bool,
+    /// The sensor recovered from creep with this reading (reported once).
+    pub recovered: bool,
+}
+
+impl ExposureReport {
+    /// Return whether the reading should be considered lower confidence.
+    pub fn is_low_confidence(&self) -> bool {
+        self.exposed || self.creep
+    }
+}
+
+/// Accumulates the exposure to high humidity from a stream of readings.
+///
+/// The humidity of a reading is assumed to persist until the next reading.
+#[derive(Debug, Clone)]
+pub struct ExposureTracker<const N: usize> {
+    config: ExposureConfig<N>,
+    /// Cumulative time above every level in milliseconds.
+    exposure_ms: [u64; N],
+    /// Previous humidity and its timestamp.
+    previous: Option<(i32, u64)>,
+    /// Time since which the humidity is at or below the recovery humidity.
+    recovering_since_ms: Option<u64>,
+    creep: bool,
+}
+
+impl<const N: usize> ExposureTracker<N> {
+    /// Create a new tracker.
+    pub fn new(config: ExposureConfig<N>) -> Self {
+        Self {
+            config,
+            exposure_ms: [0; N],
+            previous: None,
+            recovering_since_ms: None,
+            creep: false,
+        }
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &ExposureConfig<N> {
+        &self.config
+    }
+
+    /// Return the cumulative time in milliseconds above every level since
+    /// the last recovery.
+    pub fn exposure_ms(&self) -> &[u64; N] {
+        &self.exposure_ms
+    }
+
+    /// Return whether creep of the humidity reading is likely.
+    pub fn is_creeping(&self) -> bool {
+        self.creep
+    }
+
+    /// Forget all readings and the accumulated exposure.
+    pub fn reset(&mut self) {
+        self.exposure_ms = [0; N];
+        self.previous = None;
+        self.recovering_since_ms = None;
+        self.creep = false;
+    }
+
+    /// Add a humidity reading taken at `timestamp_ms` (milliseconds of a
+    /// monotonic clock) and return the exposure state.
+    pub fn update(&mut self, humidity: Humidity, timestamp_ms: u64) -> ExposureReport {
+        let humidity = humidity.as_millipercent();
+        if let Some((previous, previous_ms)) = self.previous {
+            let elapsed_ms = timestamp_ms.saturating_sub(previous_ms);
+            for (level, exposure_ms) in self.config.levels.iter().zip(&mut self.exposure_ms) {
+                if previous > level.humidity {
+                    *exposure_ms = exposure_ms.saturating_add(elapsed_ms);
+                }
+            }
+        }
+        self.previous = Some((humidity, timestamp_ms));
+
+        let exceeded = self
+            .config
+            .levels
+            .iter()
+            .zip(&self.exposure_ms)
+            .any(|(level, exposure_ms)| *exposure_ms >= u64::from(level.max_exposure_ms));
+        self.creep |= exceeded;
+
+        let mut recovered = false;
+        if humidity <= self.config.recovery_humidity {
+            let since_ms = *self.recovering_since_ms.get_or_insert(timestamp_ms);
+            if timestamp_ms.saturating_sub(since_ms) >= u64::from(self.config.recovery_ms) {
+                recovered = self.creep;
+                self.creep = false;
+                self.exposure_ms = [0; N];
+            }
+        } else {
+            self.recovering_since_ms = None;
+        }
+
+        ExposureReport {
+            exposed: self
+                .config
+                .levels
+                .iter()
+                .any(|level| humidity > level.humidity),
+            creep: self.creep,
+            recovered,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: ExposureConfig<2> = ExposureConfig {
+        levels: [
+            ExposureLevel {
+                humidity: 80_000,
+                max_exposure_ms: 10_000,
+            },
+            ExposureLevel {
+                humidity: 90_000,
+                max_exposure_ms: 2_000,
+            },
+        ],
+        recovery_humidity: 60_000,
+        recovery_ms: 5_000,
+    };
+
+    fn h(humidity: i32) -> Humidity {
+        Humidity::from_millipercent(humidity)
+    }
+
+    #[test]
+    fn normal() {
+        let mut tracker = ExposureTracker::new(CONFIG);
+        for i in 0..10 {
+            let report = tracker.update(h(80_000), i * 10_000);
+            assert_eq!(report, ExposureReport::default());
+            assert!(!report.is_low_confidence());
+        }
+        assert_eq!(tracker.exposure_ms(), &[0, 0]);
+    }
+
+    #[test]
+    fn cumulative_exposure() {
+        let mut tracker = ExposureTracker::new(CONFIG);
+        let report = tracker.update(h(85_000), 0);
+        assert!(report.exposed);
+        assert!(!report.creep);
+        assert!(report.is_low_confidence());
+
+        // Exposure is cumulative across separate periods
+        tracker.update(h(70_000), 6_000);
+        let report = tracker.update(h(85_000), 7_000);
+        assert!(report.exposed);
+        assert!(!report.creep);
+        assert_eq!(tracker.exposure_ms(), &[6_000, 0]);
+        let report = tracker.update(h(70_000), 11_000);
+        assert_eq!(
+            report,
+            ExposureReport {
+                exposed: false,
+                creep: true,
+                recovered: false,
+            }
+        );
+        assert!(report.is_low_confidence());
+        assert!(tracker.is_creeping());
+    }
+
+    #[test]
+    fn levels() {
+        let mut tracker = ExposureTracker::new(CONFIG);
+        tracker.update(h(95_000), 0);
+        assert!(!tracker.update(h(95_000), 1_000).creep);
+        assert!(tracker.update(h(95_000), 2_000).creep);
+        assert_eq!(tracker.exposure_ms(), &[2_000, 2_000]);
+    }
+
+    #[test]
+    fn recovery() {
+        let mut tracker = ExposureTracker::new(CONFIG);
+        tracker.update(h(95_000), 0);
+        assert!(tracker.update(h(50_000), 3_000).creep);
+        // Above the recovery humidity: Recovery restarts
+        assert!(tracker.update(h(65_000), 7_000).creep);
+        assert!(tracker.update(h(50_000), 8_000).creep);
+        let report = tracker.update(h(50_000), 13_000);
+        assert_eq!(
+            report,
+            ExposureReport {
+                exposed: false,
+                creep: false,
+                recovered: true,
+            }
+        );
+        assert_eq!(tracker.exposure_ms(), &[0, 0]);
+        // Reported once
+        assert_eq!(tracker.update(h(50_000), 14_000), ExposureReport::default());
+
+        // Recovery without creep clears the exposure silently
+        tracker.update(h(85_000), 14_000);
+        tracker.update(h(50_000), 15_000);
+        assert_eq!(tracker.exposure_ms(), &[1_000, 0]);
+        assert_eq!(tracker.update(h(50_000), 20_000), ExposureReport::default());
+        assert_eq!(tracker.exposure_ms(), &[0, 0]);
+
+        tracker.update(h(95_000), 20_000);
+        tracker.reset();
+        assert_eq!(tracker.exposure_ms(), &[0, 0]);
+        assert!(!tracker.is_creeping());
+    }
+}
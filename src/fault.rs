@@ -0,0 +1,532 @@
+//! Fault injection for testing error handling.
+//!
+//! [`FaultyI2c`] wraps any I²C bus that talks to an SHTCx sensor and makes the
+//! sensor misbehave on demand. Faults can be injected immediately, scheduled
+//! for a specific transaction (counted from zero) or triggered randomly at
+//! configurable rates using a seeded (and thus reproducible) pseudo random
+//! number generator.
+//!
+//! ```
+//! # use embedded_hal_mock::eh1::{delay::NoopDelay, i2c::{Mock as I2cMock, Transaction}};
+//! use shtcx::{fault::{Fault, FaultyI2c}, Error, PowerMode};
+//!
+//! # let i2c = I2cMock::new(&[
+//! #     Transaction::write(0x70, vec![0x78, 0x66]),
+//! #     Transaction::read(0x70, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+//! # ]);
+//! let mut bus: FaultyI2c<_> = FaultyI2c::new(i2c);
+//! // Corrupt the CRC of the measurement result (transaction 0 is the
+//! // measurement command, transaction 1 reads the result)
+//! bus.schedule(1, Fault::CorruptCrc).unwrap();
+//!
+//! let mut sht = shtcx::shtc3(bus);
+//! let result = sht.measure(PowerMode::NormalMode, &mut NoopDelay);
+//! assert_eq!(result, Err(Error::Crc));
+//! # sht.destroy().into_inner().done();
+//! ```
+
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// The I²C general call address.
+const GENERAL_CALL_ADDRESS: u8 = 0x00;
+
+/// The general call reset command byte.
+const GENERAL_CALL_RESET: u8 = 0x06;
+
+/// The wakeup command (see datasheet 5.3).
+const WAKEUP_COMMAND: [u8; 2] = [0x35, 0x17];
+
+/// A fault that can be injected into the communication with the sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Flip the CRC bytes of the next read.
+    CorruptCrc,
+    /// Don't acknowledge the address of the next transaction.
+    NackAddress,
+    /// Pretend that the measurement takes longer than specified: The next
+    /// `n` reads are not acknowledged.
+    Stretch(u8),
+    /// Return the same data (with valid CRC) for every read until the faults
+    /// are [cleared](FaultyI2c::clear).
+    StuckValue,
+    /// The sensor unexpectedly enters sleep mode. Every transaction is not
+    /// acknowledged until a wakeup command is sent.
+    Sleep,
+    /// The sensor locks up. Every transaction is not acknowledged until a
+    /// general call reset is sent.
+    LockUp,
+}
+
+/// Rates at which faults are randomly injected, in 1/1000 per transaction.
+///
+/// A rate of 0 (the default) disables random injection of that fault, a rate
+/// of 1000 injects it into every transaction.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FaultRates {
+    /// Rate of [`Fault::CorruptCrc`].
+    pub corrupt_crc: u16,
+    /// Rate of [`Fault::NackAddress`].
+    pub nack_address: u16,
+    /// Rate of [`Fault::Stretch`] (for a single read).
+    pub stretch: u16,
+    /// Rate of [`Fault::StuckValue`].
+    pub stuck_value: u16,
+    /// Rate of [`Fault::Sleep`].
+    pub sleep: u16,
+    /// Rate of [`Fault::LockUp`].
+    pub lock_up: u16,
+}
+
+/// Error returned by [`FaultyI2c`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultError<E> {
+    /// Error returned by the wrapped I²C bus.
+    Bus(E),
+    /// Error caused by an injected fault.
+    Injected(ErrorKind),
+}
+
+impl<E: i2c::Error> i2c::Error for FaultError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            FaultError::Bus(e) => e.kind(),
+            FaultError::Injected(kind) => *kind,
+        }
+    }
+}
+
+/// Xorshift32 pseudo random number generator.
+#[derive(Debug)]
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        // Xorshift gets stuck at 0
+        Self(if seed == 0 { 0x2545_f491 } else { seed })
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// Return true with the given probability (in 1/1000).
+    fn chance(&mut self, rate: u16) -> bool {
+        rate > 0 && self.next() % 1000 < u32::from(rate)
+    }
+}
+
+/// I²C bus wrapper that injects faults into the communication with an SHTCx
+/// sensor.
+///
+/// Up to `N` faults can be scheduled in advance.
+#[derive(Debug)]
+pub struct FaultyI2c<I2C, const N: usize = 8> {
+    /// The wrapped I²C bus.
+    i2c: I2C,
+    /// Number of transactions started so far.
+    transactions: u32,
+    /// Scheduled faults (transaction index, fault).
+    schedule: [Option<(u32, Fault)>; N],
+    /// Random fault injection rates.
+    rates: FaultRates,
+    rng: Rng,
+    /// Armed faults and simulated sensor state.
+    corrupt_crc: bool,
+    nack_address: bool,
+    stretch: u8,
+    stuck: bool,
+    stuck_data: Option<([u8; 6], usize)>,
+    asleep: bool,
+    locked_up: bool,
+}
+
+impl<I2C, const N: usize> FaultyI2c<I2C, N> {
+    /// Wrap an I²C bus. No faults are injected until configured.
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            transactions: 0,
+            schedule: [None; N],
+            rates: FaultRates::default(),
+            rng: Rng::new(0),
+            corrupt_crc: false,
+            nack_address: false,
+            stretch: 0,
+            stuck: false,
+            stuck_data: None,
+            asleep: false,
+            locked_up: false,
+        }
+    }
+
+    /// Destroy the wrapper, return the wrapped I²C bus.
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+
+    /// Return the number of transactions started so far.
+    pub fn transactions(&self) -> u32 {
+        self.transactions
+    }
+
+    /// Inject a fault into the next transaction.
+    pub fn inject(&mut self, fault: Fault) {
+        match fault {
+            Fault::CorruptCrc => self.corrupt_crc = true,
+            Fault::NackAddress => self.nack_address = true,
+            Fault::Stretch(n) => self.stretch = self.stretch.saturating_add(n),
+            Fault::StuckValue => self.stuck = true,
+            Fault::Sleep => self.asleep = true,
+            Fault::LockUp => self.locked_up = true,
+        }
+    }
+
+    /// Schedule a fault for the transaction with the given index.
+    ///
+    /// If the schedule is full, the fault is returned as error.
+    pub fn schedule(&mut self, transaction: u32, fault: Fault) -> Result<(), Fault> {
+        match self.schedule.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((transaction, fault));
+                Ok(())
+            }
+            None => Err(fault),
+        }
+    }
+
+    /// Randomly inject faults at the given rates, using a pseudo random number
+    /// generator initialized with `seed`.
+    pub fn set_rates(&mut self, rates: FaultRates, seed: u32) {
+        self.rates = rates;
+        self.rng = Rng::new(seed);
+    }
+
+    /// Remove all armed and scheduled faults and disable random injection.
+    ///
+    /// The simulated sensor state (sleeping or locked up) is cleared as well.
+    pub fn clear(&mut self) {
+        self.schedule = [None; N];
+        self.rates = FaultRates::default();
+        self.corrupt_crc = false;
+        self.nack_address = false;
+        self.stretch = 0;
+        self.stuck = false;
+        self.stuck_data = None;
+        self.asleep = false;
+        self.locked_up = false;
+    }
+
+    /// Start a new transaction: Arm scheduled and random faults, then decide
+    /// whether the transaction reaches the wrapped bus.
+    ///
+    /// `write` is the first chunk of data written, `reads` tells whether the
+    /// transaction contains a read operation.
+    fn begin<E>(
+        &mut self,
+        address: u8,
+        write: Option<&[u8]>,
+        reads: bool,
+    ) -> Result<(), FaultError<E>> {
+        let index = self.transactions;
+        self.transactions = self.transactions.wrapping_add(1);
+
+        while let Some(slot) = self
+            .schedule
+            .iter_mut()
+            .find(|slot| matches!(slot, Some((at, _)) if *at == index))
+        {
+            if let Some((_, fault)) = slot.take() {
+                self.inject(fault);
+            }
+        }
+        let rates = self.rates;
+        self.corrupt_crc |= self.rng.chance(rates.corrupt_crc);
+        self.nack_address |= self.rng.chance(rates.nack_address);
+        if self.rng.chance(rates.stretch) {
+            self.stretch = self.stretch.saturating_add(1);
+        }
+        self.stuck |= self.rng.chance(rates.stuck_value);
+        self.asleep |= self.rng.chance(rates.sleep);
+        self.locked_up |= self.rng.chance(rates.lock_up);
+
+        let nack = FaultError::Injected(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        if self.locked_up {
+            if address == GENERAL_CALL_ADDRESS && write == Some(&[GENERAL_CALL_RESET]) {
+                self.locked_up = false;
+                self.asleep = false;
+                return Ok(());
+            }
+            return Err(nack);
+        }
+        if self.asleep {
+            if write == Some(&WAKEUP_COMMAND) {
+                self.asleep = false;
+                return Ok(());
+            }
+            return Err(nack);
+        }
+        if self.nack_address {
+            self.nack_address = false;
+            return Err(nack);
+        }
+        if reads && self.stretch > 0 {
+            self.stretch -= 1;
+            return Err(nack);
+        }
+        Ok(())
+    }
+
+    /// Apply data faults to a buffer that was read from the wrapped bus.
+    fn after_read(&mut self, buf: &mut [u8]) {
+        if self.stuck {
+            match self.stuck_data {
+                Some((data, len)) => {
+                    let len = len.min(buf.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                }
+                None => {
+                    let mut data = [0; 6];
+                    let len = buf.len().min(data.len());
+                    data[..len].copy_from_slice(&buf[..len]);
+                    self.stuck_data = Some((data, len));
+                }
+            }
+        }
+        if self.corrupt_crc {
+            self.corrupt_crc = false;
+            for crc in buf.iter_mut().skip(2).step_by(3) {
+                *crc ^= 0xff;
+            }
+        }
+    }
+}
+
+impl<I2C: ErrorType, const N: usize> ErrorType for FaultyI2c<I2C, N> {
+    type Error = FaultError<I2C::Error>;
+}
+
+impl<I2C: I2c, const N: usize> I2c for FaultyI2c<I2C, N> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.begin(address, None, true)?;
+        self.i2c.read(address, read).map_err(FaultError::Bus)?;
+        self.after_read(read);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.begin(address, Some(write), false)?;
+        self.i2c.write(address, write).map_err(FaultError::Bus)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.begin(address, Some(write), true)?;
+        self.i2c
+            .write_read(address, write, read)
+            .map_err(FaultError::Bus)?;
+        self.after_read(read);
+        Ok(())
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let write = operations.iter().find_map(|op| match op {
+            Operation::Write(bytes) => Some(*bytes),
+            Operation::Read(_) => None,
+        });
+        let reads = operations.iter().any(|op| matches!(op, Operation::Read(_)));
+        self.begin(address, write, reads)?;
+        self.i2c
+            .transaction(address, operations)
+            .map_err(FaultError::Bus)?;
+        for op in operations.iter_mut() {
+            if let Operation::Read(buf) = op {
+                self.after_read(buf);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, Error, LowPower, PowerMode};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    /// Measurement result from the datasheet (section 5.4).
+    const RESULT: [u8; 6] = [0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c];
+
+    const NACK: FaultError<ErrorKind> =
+        FaultError::Injected(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+
+    fn measurement() -> [Transaction; 2] {
+        [
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, RESULT.to_vec()),
+        ]
+    }
+
+    #[test]
+    fn no_faults() {
+        let mock = I2cMock::new(&measurement());
+        let mut sht = shtc3(FaultyI2c::<_>::new(mock));
+        let measurement = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+        let bus = sht.destroy();
+        assert_eq!(bus.transactions(), 2);
+        bus.into_inner().done();
+    }
+
+    #[test]
+    fn corrupt_crc() {
+        let mock = I2cMock::new(&measurement());
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.schedule(1, Fault::CorruptCrc).unwrap();
+        let mut sht = shtc3(bus);
+        let err = sht
+            .measure(PowerMode::NormalMode, &mut NoopDelay)
+            .unwrap_err();
+        assert_eq!(err, Error::Crc);
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn nack_address() {
+        let mock = I2cMock::new(&[]);
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.inject(Fault::NackAddress);
+        let mut sht = shtc3(bus);
+        let err = sht
+            .measure(PowerMode::NormalMode, &mut NoopDelay)
+            .unwrap_err();
+        assert_eq!(err, Error::I2c(NACK));
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn stretch() {
+        let mock = I2cMock::new(&[
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, RESULT.to_vec()),
+        ]);
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.inject(Fault::Stretch(2));
+        let mut sht = shtc3(bus);
+        let pending = sht.start_measurement(PowerMode::NormalMode, 0).unwrap();
+        assert_eq!(sht.get_measurement_result(), Err(Error::I2c(NACK)));
+        let (pending, err) = pending.poll(&mut sht).unwrap_err();
+        assert_eq!(err, nb::Error::WouldBlock);
+        assert!(pending.finish(&mut sht).is_ok());
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn stuck_value() {
+        let other = [0x00, 0x00, 0x81, 0x00, 0x00, 0x81];
+        let mock = I2cMock::new(&[
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, RESULT.to_vec()),
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, other.to_vec()),
+        ]);
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.inject(Fault::StuckValue);
+        let mut sht = shtc3(bus);
+        let first = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        let second = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(first, second);
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn sleep_until_wakeup() {
+        let mut expectations = vec![Transaction::write(SHT_ADDR, vec![0x35, 0x17])];
+        expectations.extend(measurement());
+        let mock = I2cMock::new(&expectations);
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.inject(Fault::Sleep);
+        let mut sht = shtc3(bus);
+        assert_eq!(
+            sht.measure(PowerMode::NormalMode, &mut NoopDelay),
+            Err(Error::I2c(NACK))
+        );
+        sht.wakeup(&mut NoopDelay).unwrap();
+        sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn lock_up_until_general_call_reset() {
+        let mut expectations = vec![Transaction::write(GENERAL_CALL_ADDRESS, vec![0x06])];
+        expectations.extend(measurement());
+        let mock = I2cMock::new(&expectations);
+        let mut bus = FaultyI2c::<_>::new(mock);
+        bus.inject(Fault::LockUp);
+        let mut sht = shtc3(bus);
+        assert_eq!(sht.wakeup(&mut NoopDelay), Err(Error::I2c(NACK)));
+        assert_eq!(sht.reset(&mut NoopDelay), Err(Error::I2c(NACK)));
+
+        let mut bus = sht.destroy();
+        bus.write(GENERAL_CALL_ADDRESS, &[GENERAL_CALL_RESET])
+            .unwrap();
+        let mut sht = shtc3(bus);
+        sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        sht.destroy().into_inner().done();
+    }
+
+    #[test]
+    fn schedule_full() {
+        let mut bus = FaultyI2c::<_, 1>::new(I2cMock::new(&[]));
+        bus.schedule(0, Fault::NackAddress).unwrap();
+        assert_eq!(bus.schedule(1, Fault::LockUp), Err(Fault::LockUp));
+        bus.clear();
+        bus.schedule(1, Fault::LockUp).unwrap();
+        bus.into_inner().done();
+    }
+
+    /// Random faults must be reproducible with the same seed.
+    #[test]
+    fn rng_is_deterministic() {
+        let sequence = |seed| {
+            let mut rng = Rng::new(seed);
+            [rng.next(), rng.next(), rng.next()]
+        };
+        assert_eq!(sequence(42), sequence(42));
+        assert_ne!(sequence(42), sequence(43));
+
+        let mut rng = Rng::new(0);
+        assert!((0..100).all(|_| !rng.chance(0)));
+        assert!((0..100).all(|_| rng.chance(1000)));
+    }
+
+    #[test]
+    fn random_lock_up() {
+        let rates = FaultRates {
+            lock_up: 1000,
+            ..Default::default()
+        };
+        let mut bus = FaultyI2c::<_>::new(I2cMock::new(&[]));
+        bus.set_rates(rates, 1);
+        assert_eq!(bus.write(SHT_ADDR, &[0x35, 0x17]), Err(NACK));
+        bus.clear();
+        bus.into_inner().done();
+    }
+}
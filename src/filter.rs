@@ -0,0 +1,471 @@
+//! Filters for smoothing series of measurements.
+//!
+//! All filters implement the [`Filter`] trait: Every new [`Measurement`] is
+//! passed to [`Filter::update`], which returns the filtered measurement.
+//! Temperature and humidity are filtered independently, using integer math on
+//! the milli-unit representation (m°C and 1/1000 %RH).
+//!
+//! - [`MovingAverage`]: Simple moving average over the last `N` measurements.
+//! - [`ExponentialMovingAverage`]: Exponential moving average with a
+//!   configurable smoothing factor.
+//! - [`Median`]: Median of the last `N` measurements, suppresses spikes.
+//! - [`Kalman`]: Simple one-dimensional Kalman filter.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::{filter::{Filter, Median}, PowerMode};
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut delay = Delay;
+//! let mut median = Median::<5>::new();
+//! loop {
+//!     let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
+//!     let filtered = median.update(measurement);
+//!     println!("{} °C", filtered.temperature.as_degrees_celsius());
+//! }
+//! ```
+
+use crate::{Humidity, Measurement, Temperature};
+
+/// A filter for a series of measurements.
+pub trait Filter {
+    /// Add a new measurement and return the filtered measurement.
+    fn update(&mut self, measurement: Measurement) -> Measurement;
+
+    /// Reset the filter to its initial state.
+    fn reset(&mut self);
+}
+
+/// Temperature and humidity in milli-units.
+type Values = [i32; 2];
+
+fn values(measurement: &Measurement) -> Values {
+    [
+        measurement.temperature.as_millidegrees_celsius(),
+        measurement.humidity.as_millipercent(),
+    ]
+}
+
+fn measurement(values: Values) -> Measurement {
+    Measurement {
+        temperature: Temperature::from_millidegrees_celsius(values[0]),
+        humidity: Humidity::from_millipercent(values[1]),
+    }
+}
+
+/// Values are scaled by this factor in the filter state to avoid rounding
+/// loss.
+const SCALE: i64 = 1000;
+
+/// Convert a scaled value back to milli-units, rounding to the nearest.
+fn unscale(value: i64) -> i32 {
+    div_round(value, SCALE)
+}
+
+/// Divide by a positive divisor, rounding to the nearest (ties towards
+/// positive infinity, like [`unscale`]).
+fn div_round(value: i64, divisor: i64) -> i32 {
+    (2 * value + divisor).div_euclid(2 * divisor) as i32
+}
+
+/// Ring buffer for the last `N` values.
+#[derive(Debug, Clone)]
+struct Window<const N: usize> {
+    values: [Values; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Window<N> {
+    const fn new() -> Self {
+        Self {
+            values: [[0; 2]; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Add new values and return the values that were dropped (if any).
+    fn push(&mut self, values: Values) -> Option<Values> {
+        if N == 0 {
+            return None;
+        }
+        let dropped = (self.len == N).then_some(self.values[self.next]);
+        self.values[self.next] = values;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        dropped
+    }
+
+    fn as_slice(&self) -> &[Values] {
+        &self.values[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
+/// Simple moving average over the last `N` measurements.
+///
+/// Until `N` measurements have been added, the average of all measurements so
+/// far is returned.
+#[derive(Debug, Clone)]
+pub struct MovingAverage<const N: usize> {
+    window: Window<N>,
+    sums: [i64; 2],
+}
+
+impl<const N: usize> MovingAverage<N> {
+    /// Create a new moving average filter.
+    pub const fn new() -> Self {
+        Self {
+            window: Window::new(),
+            sums: [0; 2],
+        }
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, measurement: Measurement) -> Measurement {
+        let new = values(&measurement);
+        if N == 0 {
+            return measurement;
+        }
+        let dropped = self.window.push(new);
+        let len = self.window.len as i64;
+        let mut average = [0; 2];
+        for i in 0..2 {
+            self.sums[i] += i64::from(new[i]) - dropped.map_or(0, |d| i64::from(d[i]));
+            average[i] = div_round(self.sums[i], len);
+        }
+        self::measurement(average)
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sums = [0; 2];
+    }
+}
+
+/// Exponential moving average.
+///
+/// Every update moves the filtered value by `alpha` towards the new
+/// measurement: `value = value + alpha · (measurement - value)`. The first
+/// measurement is returned unchanged.
+#[derive(Debug, Clone)]
+pub struct ExponentialMovingAverage {
+    /// Smoothing factor in 1/1000.
+    alpha: u16,
+    /// Filtered values (scaled).
+    state: Option<[i64; 2]>,
+}
+
+impl ExponentialMovingAverage {
+    /// Create a new exponential moving average filter with the smoothing
+    /// factor `alpha_permille` (in 1/1000, clamped to `1..=1000`).
+    ///
+    /// Small values result in strong smoothing, a value of 1000 disables
+    /// smoothing.
+    pub fn new(alpha_permille: u16) -> Self {
+        Self {
+            alpha: alpha_permille.clamp(1, 1000),
+            state: None,
+        }
+    }
+
+    /// Return the smoothing factor in 1/1000.
+    pub fn alpha_permille(&self) -> u16 {
+        self.alpha
+    }
+}
+
+impl Filter for ExponentialMovingAverage {
+    fn update(&mut self, measurement: Measurement) -> Measurement {
+        let new = values(&measurement).map(|v| i64::from(v) * SCALE);
+        let state = self.state.get_or_insert(new);
+        let alpha = i64::from(self.alpha);
+        for i in 0..2 {
+            state[i] += alpha * (new[i] - state[i]) / 1000;
+        }
+        self::measurement(state.map(unscale))
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Median of the last `N` measurements.
+///
+/// The median suppresses single spikes, as long as they make up less than
+/// half of the window. For an even number of measurements, the mean of the
+/// two middle values is returned (rounded like [`MovingAverage`]).
+#[derive(Debug, Clone)]
+pub struct Median<const N: usize> {
+    window: Window<N>,
+}
+
+impl<const N: usize> Median<N> {
+    /// Create a new median filter.
+    pub const fn new() -> Self {
+        Self {
+            window: Window::new(),
+        }
+    }
+}
+
+impl<const N: usize> Default for Median<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for Median<N> {
+    fn update(&mut self, measurement: Measurement) -> Measurement {
+        if N == 0 {
+            return measurement;
+        }
+        self.window.push(values(&measurement));
+        let window = self.window.as_slice();
+        let len = window.len();
+        let mut median = [0; 2];
+        for (i, median) in median.iter_mut().enumerate() {
+            let mut sorted = [0; N];
+            for (sorted, values) in sorted.iter_mut().zip(window) {
+                *sorted = values[i];
+            }
+            let sorted = &mut sorted[..len];
+            sorted.sort_unstable();
+            *median = if len.is_multiple_of(2) {
+                div_round(
+                    i64::from(sorted[len / 2 - 1]) + i64::from(sorted[len / 2]),
+                    2,
+                )
+            } else {
+                sorted[len / 2]
+            };
+        }
+        self::measurement(median)
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+/// Noise parameters of a [`Kalman`] filter, as variances in squared
+/// milli-units (e.g. (m°C)²).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KalmanNoise {
+    /// Variance of the change of the true value between two measurements.
+    pub process: u32,
+    /// Variance of the measurement noise.
+    pub measurement: u32,
+}
+
+/// Simple one-dimensional Kalman filter, assuming a constant value with
+/// random changes.
+///
+/// The ratio of process noise to measurement noise determines how quickly
+/// the filter follows changes: A low process noise results in strong
+/// smoothing. The first measurement is returned unchanged.
+#[derive(Debug, Clone)]
+pub struct Kalman {
+    noise: [KalmanNoise; 2],
+    /// Estimated values (scaled) and their error variances.
+    state: Option<([i64; 2], [u64; 2])>,
+}
+
+impl Kalman {
+    /// Create a new Kalman filter with the noise parameters for temperature
+    /// (in (m°C)²) and humidity (in (1/1000 %RH)²).
+    pub fn new(temperature_noise: KalmanNoise, humidity_noise: KalmanNoise) -> Self {
+        Self {
+            noise: [temperature_noise, humidity_noise],
+            state: None,
+        }
+    }
+}
+
+impl Filter for Kalman {
+    fn update(&mut self, measurement: Measurement) -> Measurement {
+        let new = values(&measurement).map(|v| i64::from(v) * SCALE);
+        let Some((estimates, variances)) = &mut self.state else {
+            let variances = self.noise.map(|noise| u64::from(noise.measurement));
+            self.state = Some((new, variances));
+            return measurement;
+        };
+        for i in 0..2 {
+            // Predict
+            let variance = variances[i] + u64::from(self.noise[i].process);
+            let total = variance + u64::from(self.noise[i].measurement);
+            // Update with the Kalman gain `variance / total`
+            if total > 0 {
+                estimates[i] += (i128::from(new[i] - estimates[i]) * i128::from(variance)
+                    / i128::from(total)) as i64;
+                variances[i] = variance * u64::from(self.noise[i].measurement) / total;
+            } else {
+                estimates[i] = new[i];
+                variances[i] = 0;
+            }
+        }
+        self::measurement(estimates.map(unscale))
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    #[test]
+    fn moving_average() {
+        let mut filter = MovingAverage::<3>::new();
+        assert_eq!(filter.update(m(1000, 50_000)), m(1000, 50_000));
+        assert_eq!(filter.update(m(2000, 51_000)), m(1500, 50_500));
+        assert_eq!(filter.update(m(3000, 52_000)), m(2000, 51_000));
+        // The first measurement drops out of the window
+        assert_eq!(filter.update(m(7000, 53_000)), m(4000, 52_000));
+        assert_eq!(filter.update(m(-1000, 0)), m(3000, 35_000));
+        filter.reset();
+        assert_eq!(filter.update(m(1000, 50_000)), m(1000, 50_000));
+    }
+
+    /// The average is rounded to the nearest, like the other filters.
+    #[test]
+    fn moving_average_rounding() {
+        let mut filter = MovingAverage::<3>::new();
+        filter.update(m(1, -1));
+        assert_eq!(filter.update(m(2, -2)), m(2, -1));
+        assert_eq!(filter.update(m(2, -2)), m(2, -2));
+        filter.reset();
+        filter.update(m(-1, 0));
+        filter.update(m(-1, 0));
+        assert_eq!(filter.update(m(0, 1)), m(-1, 0));
+    }
+
+    #[test]
+    fn exponential_moving_average() {
+        let mut filter = ExponentialMovingAverage::new(250);
+        assert_eq!(filter.update(m(20_000, 40_000)), m(20_000, 40_000));
+        assert_eq!(filter.update(m(24_000, 40_000)), m(21_000, 40_000));
+        assert_eq!(filter.update(m(24_000, 48_000)), m(21_750, 42_000));
+        filter.reset();
+        assert_eq!(filter.update(m(0, 0)), m(0, 0));
+
+        // Alpha is clamped, 1000 disables smoothing
+        let mut filter = ExponentialMovingAverage::new(2000);
+        assert_eq!(filter.alpha_permille(), 1000);
+        filter.update(m(20_000, 40_000));
+        assert_eq!(filter.update(m(24_000, 30_000)), m(24_000, 30_000));
+    }
+
+    /// Small changes aren't lost to rounding.
+    #[test]
+    fn exponential_moving_average_precision() {
+        let mut filter = ExponentialMovingAverage::new(100);
+        filter.update(m(0, 0));
+        let mut filtered = m(0, 0);
+        for _ in 0..100 {
+            filtered = filter.update(m(5, 5));
+        }
+        assert_eq!(filtered, m(5, 5));
+    }
+
+    #[test]
+    fn median() {
+        let mut filter = Median::<3>::new();
+        assert_eq!(filter.update(m(20_000, 40_000)), m(20_000, 40_000));
+        // Even number of values: mean of the middle values
+        assert_eq!(filter.update(m(21_000, 41_000)), m(20_500, 40_500));
+        // Spikes are suppressed
+        assert_eq!(filter.update(m(85_000, 0)), m(21_000, 40_000));
+        assert_eq!(filter.update(m(22_000, 42_000)), m(22_000, 41_000));
+        filter.reset();
+        assert_eq!(filter.update(m(85_000, 0)), m(85_000, 0));
+    }
+
+    /// The mean of the middle values is rounded like the moving average.
+    #[test]
+    fn median_rounding() {
+        let mut median = Median::<4>::new();
+        let mut average = MovingAverage::<4>::new();
+        for (first, second) in [
+            (m(-20_001, 1), m(-20_004, 2)),
+            (m(-3, -3), m(-2, -4)),
+            (m(-1, 0), m(-2, -1)),
+        ] {
+            median.reset();
+            average.reset();
+            median.update(first);
+            average.update(first);
+            assert_eq!(median.update(second), average.update(second));
+        }
+        median.reset();
+        median.update(m(-20_001, 1));
+        assert_eq!(median.update(m(-20_004, 2)), m(-20_002, 2));
+    }
+
+    #[test]
+    fn kalman() {
+        let noise = KalmanNoise {
+            process: 100,
+            measurement: 10_000,
+        };
+        let mut filter = Kalman::new(noise, noise);
+        assert_eq!(filter.update(m(20_000, 40_000)), m(20_000, 40_000));
+        // Gain is 10100 / 20100
+        assert_eq!(filter.update(m(21_000, 40_000)), m(20_502, 40_000));
+
+        // Converges to a constant value, with decreasing gain
+        let mut filtered = m(0, 0);
+        for _ in 0..1000 {
+            filtered = filter.update(m(21_000, 40_000));
+        }
+        assert_eq!(filtered, m(21_000, 40_000));
+        let filtered = filter.update(m(22_000, 40_000));
+        let step = filtered.temperature.as_millidegrees_celsius() - 21_000;
+        assert!(step > 0 && step < 200, "step: {}", step);
+
+        filter.reset();
+        assert_eq!(filter.update(m(0, 0)), m(0, 0));
+    }
+
+    /// Without measurement noise, the filter follows the measurements.
+    #[test]
+    fn kalman_no_noise() {
+        let noise = KalmanNoise {
+            process: 0,
+            measurement: 0,
+        };
+        let mut filter = Kalman::new(noise, noise);
+        filter.update(m(20_000, 40_000));
+        assert_eq!(filter.update(m(21_000, 41_000)), m(21_000, 41_000));
+    }
+
+    #[test]
+    fn empty_window() {
+        let mut filter = MovingAverage::<0>::new();
+        assert_eq!(filter.update(m(1, 2)), m(1, 2));
+        let mut filter = Median::<0>::new();
+        assert_eq!(filter.update(m(1, 2)), m(1, 2));
+    }
+}
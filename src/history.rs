@@ -0,0 +1,357 @@
+//! Fixed-capacity history of measurements.
+//!
+//! [`History`] is a ring buffer for the last `N` measurements that doesn't
+//! need an allocator. Besides iterating over the measurements, it provides
+//! the minimum, maximum and mean of the buffered measurements and the rate of
+//! change (based on a linear regression over the timestamped measurements).
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::{history::History, PowerMode};
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! # fn now_ms() -> u64 { 0 }
+//! let mut delay = Delay;
+//! let mut history = History::<60>::new();
+//! loop {
+//!     let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
+//!     history.push_at(measurement, now_ms());
+//!     if let Some(rate) = history.rate_of_change() {
+//!         println!("{} m°C/min", rate.temperature_per_minute);
+//!     }
+//! }
+//! ```
+
+use crate::{Humidity, Measurement, Temperature};
+
+/// A measurement in a [`History`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The measurement.
+    pub measurement: Measurement,
+    /// The time of the measurement in milliseconds, if known.
+    pub timestamp_ms: Option<u64>,
+}
+
+/// Rate of change of temperature and humidity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RateOfChange {
+    /// Temperature change in milli-degrees celsius per minute.
+    pub temperature_per_minute: i32,
+    /// Humidity change in 1/1000 %RH per minute.
+    pub humidity_per_minute: i32,
+}
+
+const EMPTY: HistoryEntry = HistoryEntry {
+    measurement: Measurement {
+        temperature: Temperature::from_millidegrees_celsius(0),
+        humidity: Humidity::from_millipercent(0),
+    },
+    timestamp_ms: None,
+};
+
+/// Ring buffer for the last `N` measurements.
+///
+/// When the buffer is full, pushing a new measurement drops the oldest one.
+#[derive(Debug, Clone)]
+pub struct History<const N: usize> {
+    entries: [HistoryEntry; N],
+    len: usize,
+    next: usize,
+    /// Sums of temperature and humidity for the mean.
+    sums: [i64; 2],
+}
+
+impl<const N: usize> History<N> {
+    /// Create an empty history.
+    pub const fn new() -> Self {
+        Self {
+            entries: [EMPTY; N],
+            len: 0,
+            next: 0,
+            sums: [0; 2],
+        }
+    }
+
+    /// Return the maximum number of measurements.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Return the number of measurements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether the history is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return whether the history is full.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Remove all measurements.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+        self.sums = [0; 2];
+    }
+
+    /// Add a measurement without timestamp.
+    ///
+    /// Return the oldest entry if it was dropped.
+    pub fn push(&mut self, measurement: Measurement) -> Option<HistoryEntry> {
+        self.push_entry(HistoryEntry {
+            measurement,
+            timestamp_ms: None,
+        })
+    }
+
+    /// Add a measurement taken at `timestamp_ms` (milliseconds of a monotonic
+    /// clock).
+    ///
+    /// Return the oldest entry if it was dropped.
+    pub fn push_at(&mut self, measurement: Measurement, timestamp_ms: u64) -> Option<HistoryEntry> {
+        self.push_entry(HistoryEntry {
+            measurement,
+            timestamp_ms: Some(timestamp_ms),
+        })
+    }
+
+    fn push_entry(&mut self, entry: HistoryEntry) -> Option<HistoryEntry> {
+        if N == 0 {
+            return Some(entry);
+        }
+        let dropped = self.is_full().then_some(self.entries[self.next]);
+        if let Some(dropped) = dropped {
+            self.add_to_sums(&dropped.measurement, -1);
+        }
+        self.add_to_sums(&entry.measurement, 1);
+        self.entries[self.next] = entry;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        dropped
+    }
+
+    fn add_to_sums(&mut self, measurement: &Measurement, sign: i64) {
+        self.sums[0] += sign * i64::from(measurement.temperature.as_millidegrees_celsius());
+        self.sums[1] += sign * i64::from(measurement.humidity.as_millipercent());
+    }
+
+    /// Return the newest entry.
+    pub fn latest(&self) -> Option<&HistoryEntry> {
+        self.iter().next()
+    }
+
+    /// Iterate over the entries, from newest to oldest.
+    ///
+    /// Use `.rev()` to iterate from oldest to newest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &HistoryEntry> + ExactSizeIterator + '_ {
+        (0..self.len).map(move |age| &self.entries[(self.next + N - 1 - age) % N])
+    }
+
+    /// Return the minimum temperature and humidity.
+    ///
+    /// Both minimums are determined separately, so they usually don't
+    /// correspond to a single measurement.
+    pub fn min(&self) -> Option<Measurement> {
+        self.fold(i32::min)
+    }
+
+    /// Return the maximum temperature and humidity.
+    ///
+    /// Both maximums are determined separately, so they usually don't
+    /// correspond to a single measurement.
+    pub fn max(&self) -> Option<Measurement> {
+        self.fold(i32::max)
+    }
+
+    fn fold(&self, f: impl Fn(i32, i32) -> i32) -> Option<Measurement> {
+        let first = self.latest()?.measurement;
+        Some(self.iter().fold(first, |acc, entry| Measurement {
+            temperature: Temperature::from_millidegrees_celsius(f(
+                acc.temperature.as_millidegrees_celsius(),
+                entry.measurement.temperature.as_millidegrees_celsius(),
+            )),
+            humidity: Humidity::from_millipercent(f(
+                acc.humidity.as_millipercent(),
+                entry.measurement.humidity.as_millipercent(),
+            )),
+        }))
+    }
+
+    /// Return the mean temperature and humidity.
+    pub fn mean(&self) -> Option<Measurement> {
+        if self.is_empty() {
+            return None;
+        }
+        let len = self.len as i64;
+        Some(Measurement {
+            temperature: Temperature::from_millidegrees_celsius((self.sums[0] / len) as i32),
+            humidity: Humidity::from_millipercent((self.sums[1] / len) as i32),
+        })
+    }
+
+    /// Return the rate of change over the buffered window.
+    ///
+    /// The rate of change is the slope of a linear regression over all
+    /// timestamped measurements. Measurements without timestamp are ignored.
+    /// Return `None` if there are less than two timestamped measurements or
+    /// if all of them have the same timestamp.
+    pub fn rate_of_change(&self) -> Option<RateOfChange> {
+        let timestamped = || {
+            self.iter()
+                .filter_map(|entry| Some((entry.timestamp_ms?, entry.measurement)))
+        };
+        let t0 = timestamped().map(|(t, _)| t).min()?;
+
+        // Sums for the linear regression, with times relative to t0
+        let (mut n, mut sum_t, mut sum_tt) = (0i128, 0i128, 0i128);
+        let (mut sum_v, mut sum_tv) = ([0i128; 2], [0i128; 2]);
+        for (timestamp_ms, measurement) in timestamped() {
+            let t = i128::from(timestamp_ms - t0);
+            let v = [
+                i128::from(measurement.temperature.as_millidegrees_celsius()),
+                i128::from(measurement.humidity.as_millipercent()),
+            ];
+            n += 1;
+            sum_t += t;
+            sum_tt += t * t;
+            for i in 0..2 {
+                sum_v[i] += v[i];
+                sum_tv[i] += t * v[i];
+            }
+        }
+        let denominator = n * sum_tt - sum_t * sum_t;
+        if denominator == 0 {
+            return None;
+        }
+        let slope_per_minute =
+            |i: usize| ((n * sum_tv[i] - sum_t * sum_v[i]) * 60_000 / denominator) as i32;
+        Some(RateOfChange {
+            temperature_per_minute: slope_per_minute(0),
+            humidity_per_minute: slope_per_minute(1),
+        })
+    }
+}
+
+impl<const N: usize> Default for History<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    #[test]
+    fn empty() {
+        let history = History::<4>::new();
+        assert!(history.is_empty());
+        assert_eq!(history.capacity(), 4);
+        assert_eq!(history.latest(), None);
+        assert_eq!(history.min(), None);
+        assert_eq!(history.max(), None);
+        assert_eq!(history.mean(), None);
+        assert_eq!(history.rate_of_change(), None);
+        assert_eq!(history.iter().count(), 0);
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let mut history = History::<3>::new();
+        for i in 0..3 {
+            assert_eq!(history.push(m(i, 0)), None);
+        }
+        assert!(history.is_full());
+        let dropped = history.push(m(3, 0)).unwrap();
+        assert_eq!(dropped.measurement, m(0, 0));
+        assert_eq!(history.len(), 3);
+
+        let newest_first: Vec<_> = history
+            .iter()
+            .map(|e| e.measurement.temperature.as_millidegrees_celsius())
+            .collect();
+        assert_eq!(newest_first, [3, 2, 1]);
+        let oldest_first: Vec<_> = history
+            .iter()
+            .rev()
+            .map(|e| e.measurement.temperature.as_millidegrees_celsius())
+            .collect();
+        assert_eq!(oldest_first, [1, 2, 3]);
+        assert_eq!(history.latest().unwrap().measurement, m(3, 0));
+
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(history.mean(), None);
+    }
+
+    #[test]
+    fn statistics() {
+        let mut history = History::<3>::new();
+        history.push(m(-5_000, 60_000));
+        history.push(m(20_000, 40_000));
+        history.push(m(22_000, 45_000));
+        history.push(m(21_000, 50_000));
+        assert_eq!(history.min(), Some(m(20_000, 40_000)));
+        assert_eq!(history.max(), Some(m(22_000, 50_000)));
+        assert_eq!(history.mean(), Some(m(21_000, 45_000)));
+    }
+
+    #[test]
+    fn rate_of_change() {
+        let mut history = History::<8>::new();
+        history.push_at(m(20_000, 50_000), 10_000);
+        assert_eq!(history.rate_of_change(), None);
+        // Not timestamped, ignored
+        history.push(m(90_000, 0));
+        history.push_at(m(20_500, 49_000), 40_000);
+        history.push_at(m(21_000, 48_000), 70_000);
+        assert_eq!(
+            history.rate_of_change(),
+            Some(RateOfChange {
+                temperature_per_minute: 1_000,
+                humidity_per_minute: -2_000,
+            })
+        );
+    }
+
+    /// Noisy values are fitted with a linear regression.
+    #[test]
+    fn rate_of_change_regression() {
+        let mut history = History::<4>::new();
+        for (t, v) in [(0, 100), (60_000, 300), (120_000, 200), (180_000, 400)] {
+            history.push_at(m(v, 0), t);
+        }
+        let rate = history.rate_of_change().unwrap();
+        assert_eq!(rate.temperature_per_minute, 80);
+        assert_eq!(rate.humidity_per_minute, 0);
+    }
+
+    #[test]
+    fn rate_of_change_same_timestamp() {
+        let mut history = History::<4>::new();
+        history.push_at(m(20_000, 0), 1_000);
+        history.push_at(m(21_000, 0), 1_000);
+        assert_eq!(history.rate_of_change(), None);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut history = History::<0>::new();
+        assert_eq!(history.push(m(1, 2)).unwrap().measurement, m(1, 2));
+        assert!(history.is_empty());
+        assert_eq!(history.latest(), None);
+    }
+}
@@ -102,21 +102,64 @@
 //! let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
 //! ```
 //!
+//! To reduce noise, several measurements can be averaged. Besides the mean,
+//! the minimum, maximum and standard deviation are returned. With
+//! `measure_averaged_trimmed`, outliers can be discarded:
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! # use shtcx::{self, PowerMode};
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut delay = Delay;
+//! let averaged = sht.measure_averaged(PowerMode::LowPower, 8, &mut delay).unwrap();
+//! println!("{} °C ± {} m°C",
+//!          averaged.mean.temperature.as_degrees_celsius(),
+//!          averaged.temperature_std_dev);
+//! ```
+//!
 //! ### Measurements (Non-Blocking)
 //!
 //! If you want to avoid blocking measurements, you can use the non-blocking
-//! commands instead. You are, however, responsible for ensuring the correct
-//! timing of the calls.
+//! commands instead. Starting a measurement returns a
+//! [`PendingMeasurement`] token. Pass in the current time (in microseconds)
+//! of a monotonic clock, so the token can tell when the result is ready.
 //!
 //! ```no_run
 //! # use linux_embedded_hal::I2cdev;
 //! # use shtcx;
 //! use shtcx::PowerMode;
 //! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! # let now_us = || 0;
 //!
-//! sht.start_measurement(PowerMode::NormalMode).unwrap();
-//! // Wait for at least `max_measurement_duration(&sht, PowerMode::NormalMode)` µs
-//! let result = sht.get_measurement_result().unwrap();
+//! let pending = sht.start_measurement(PowerMode::NormalMode, now_us()).unwrap();
+//! while !pending.is_ready(now_us()) {
+//!     // Do other stuff
+//! }
+//! let result = pending.finish(&mut sht).unwrap();
+//! ```
+//!
+//! The token only yields the kind of result that was started, i.e. a
+//! [`Measurement`] for `start_measurement`, a [`Temperature`] for
+//! `start_temperature_measurement` and a [`Humidity`] for
+//! `start_humidity_measurement`.
+//!
+//! Alternatively, you can read the results without a token (e.g. using
+//! [`get_measurement_result`](crate::ShtCx::get_measurement_result())). You
+//! are, however, responsible for ensuring the correct timing of the calls and
+//! for reading the right kind of result.
+//!
+//! For superloop style applications, the `poll_*` variants (e.g.
+//! [`poll_measurement_result`](crate::ShtCx::poll_measurement_result())) return
+//! an [`nb::Result`], reporting [`WouldBlock`](nb::Error::WouldBlock) while
+//! the measurement is still in progress. This way, no delay and no knowledge
+//! of the measurement duration is required:
+//!
+//! ```no_run
+//! # use linux_embedded_hal::I2cdev;
+//! # use shtcx::{self, PowerMode};
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let _pending = sht.start_measurement(PowerMode::NormalMode, 0).unwrap();
+//! let result = nb::block!(sht.poll_measurement_result()).unwrap();
 //! ```
 //!
 //! In non-blocking mode, if desired, you can also read the raw 16-bit
@@ -157,6 +200,39 @@
 //! [`wakeup`](trait.LowPower.html#tymethod.wakeup) while the sensor is in
 //! sleep mode will result in an error.
 //!
+//! To avoid managing the sleep state manually, the SHTC3 driver can be turned
+//! into an [`AutoSleep`] driver. It keeps track of whether the sensor is
+//! asleep, wakes it up before every command and puts it back to sleep
+//! according to a [`SleepPolicy`]:
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! # use shtcx::{self, PowerMode};
+//! use shtcx::SleepPolicy;
+//! # let sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! let mut delay = Delay;
+//! let mut sht = sht.into_auto_sleep(SleepPolicy::Always);
+//! let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
+//! ```
+//!
+//! ### Power Gating
+//!
+//! Sensors without sleep mode (e.g. the SHTC1 or SHTW2) can be powered from a
+//! GPIO pin instead. The [`PowerGated`] driver powers up the sensor before
+//! every measurement and cuts the power afterwards. If the sensor stops
+//! responding, it can be power cycled with
+//! [`recover`](PowerGated::recover).
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev, SysfsPin};
+//! # use shtcx::{self, PowerMode};
+//! # let sht = shtcx::shtc1(I2cdev::new("/dev/i2c-1").unwrap());
+//! # let supply_pin = SysfsPin::new(17);
+//! let mut delay = Delay;
+//! let mut sht = sht.into_power_gated(supply_pin);
+//! let measurement = sht.measure(PowerMode::NormalMode, &mut delay).unwrap();
+//! ```
+//!
 //! ### Soft Reset
 //!
 //! The SHTCx provides a soft reset mechanism that forces the system into a
@@ -186,11 +262,139 @@
 //! tolerances will be ensured, so using the generic driver with the SHTC3 will
 //! result in slightly slower measurements (and slightly higher power
 //! consumption) than when using the SHTC3 specific driver.
+//!
+//! ### Adaptive Power Mode
+//!
+//! The [`adaptive`] module provides a helper that measures in low power mode
+//! while the temperature is stable and switches to normal mode while it
+//! changes quickly or is noisy.
+//!
+//! ### Filtering
+//!
+//! The [`filter`] module provides moving average, exponential moving average,
+//! median and Kalman filters for smoothing series of measurements.
+//!
+//! ### History
+//!
+//! The [`history`] module provides a fixed-capacity ring buffer for the last
+//! measurements, with statistics and the rate of change over the buffered
+//! window. It doesn't need an allocator.
+//!
+//! ### Aggregation
+//!
+//! For logging to constrained storage, the [`aggregate`] module groups
+//! timestamped measurements into fixed time buckets and emits the count,
+//! minimum, maximum and mean of every bucket.
+//!
+//! ### Plausibility Checks
+//!
+//! A failing sensor may return garbage with valid CRCs. The [`validate`]
+//! module detects saturated raw values, out-of-spec values, stuck readings
+//! and implausible step changes.
+//!
+//! ### Trend Detection
+//!
+//! The [`trend`] module calculates the rate of change of temperature and
+//! humidity over the last measurements and classifies it as rising, falling
+//! or steady.
+//!
+//! ### Alerts
+//!
+//! The sensors don't have an alert pin. The [`alert`] module evaluates
+//! measurements against thresholds for temperature, humidity and dew point,
+//! with hysteresis and a minimum dwell time.
+//!
+//! ### Self-Heating
+//!
+//! At high sampling rates, the sensor heats itself up. The [`self_heating`]
+//! module tracks the measurement duty cycle, corrects the resulting
+//! temperature rise and warns or throttles when a duty cycle limit is
+//! exceeded.
+//!
+//! ### Humidity Exposure
+//!
+//! Prolonged exposure to high humidity causes creep of the humidity reading.
+//! The [`exposure`] module accumulates the time spent above configurable
+//! humidity levels, flags readings as lower confidence and reports the
+//! recovery.
+//!
+//! ### Measurement Accuracy
+//!
+//! The [`accuracy`] module provides the typical and maximum datasheet
+//! tolerances for a measured temperature and humidity. With
+//! `measure_with_metadata`, a measurement is returned together with the power
+//! mode, the sensor class and these tolerances.
+//!
+//! ### Energy Consumption
+//!
+//! The [`energy`] module estimates the average current and the energy per
+//! measurement for a given sensor class, power mode, sampling period and
+//! sleep strategy, based on datasheet figures.
+//!
+//! ### Sans-IO Protocol
+//!
+//! If your I²C stack can't provide an [`I2c`] implementation (e.g. because
+//! it is DMA driven), the [`protocol`] module provides the command bytes for
+//! every operation and functions to validate and parse the sensor responses.
+//!
+//! ### Calibration
+//!
+//! Static temperature and humidity corrections can be set with
+//! [`ShtCx::set_calibration`]. The [`calibration`] module can also fit
+//! linear or piecewise-linear corrections to measurements against a
+//! reference, which can be set on the driver the same way.
+//!
+//! With the `embedded-storage` feature enabled, the `storage` module stores
+//! calibration data in a versioned, CRC-protected record through the
+//! [`embedded-storage`](https://docs.rs/embedded-storage) traits.
+//!
+//! ### Statistics
+//!
+//! With the `statistics` feature enabled, the driver counts the commands
+//! sent, successful measurements, CRC errors, I²C errors (by kind), resets,
+//! wakeups and retries. Use `ShtCx::statistics` to get a snapshot of the
+//! counters and `ShtCx::reset_statistics` to reset them. Without the
+//! feature, the counters don't use any memory.
+//!
+//! ### Fault Injection
+//!
+//! To test how your application handles a misbehaving sensor, wrap the I²C
+//! bus in a [`FaultyI2c`](fault::FaultyI2c). See the [`fault`] module for
+//! details.
+//!
+//! ### Recording and Replaying Bus Traffic
+//!
+//! With the `std` feature enabled, the `record` module provides a `Recorder`
+//! that logs all I²C traffic between the driver and a sensor to a text file,
+//! and a `Replayer` that reproduces such a recording without hardware.
 #![deny(unsafe_code, missing_docs)]
-#![cfg_attr(not(test), no_std)]
-
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod accuracy;
+pub mod adaptive;
+pub mod aggregate;
+pub mod alert;
+mod auto_sleep;
+mod averaging;
+pub mod calibration;
 mod crc;
+pub mod energy;
+pub mod exposure;
+pub mod fault;
+pub mod filter;
+pub mod history;
+mod pending;
+mod power_gate;
+pub mod protocol;
+#[cfg(any(test, feature = "std"))]
+pub mod record;
+pub mod self_heating;
+mod statistics;
+#[cfg(any(test, feature = "embedded-storage"))]
+pub mod storage;
+pub mod trend;
 mod types;
+pub mod validate;
 
 use core::marker::PhantomData;
 
@@ -199,17 +403,18 @@ use embedded_hal::{
     i2c::{self, I2c, SevenBitAddress},
 };
 
-use crc::crc8;
+pub use auto_sleep::{AutoSleep, SleepPolicy};
+pub use averaging::AveragedMeasurement;
+use calibration::CalibrationProfile;
+pub use pending::{MeasurementKind, PendingMeasurement, PollResult};
+pub use power_gate::{PowerGateError, PowerGated};
+pub use protocol::MeasurementOrder;
+use protocol::{Command, CrcError};
+use statistics::Counters;
+#[cfg(any(test, feature = "statistics"))]
+pub use statistics::{I2cErrorCounts, Statistics};
 pub use types::*;
 
-/// Whether temperature or humidity is returned first when doing a measurement.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum MeasurementOrder {
-    TemperatureFirst,
-    HumidityFirst,
-}
-use MeasurementOrder::*;
-
 /// Measurement power mode: Normal mode or low power mode.
 ///
 /// The sensors provides a low power measurement mode. Using the low power mode
@@ -250,48 +455,9 @@ where
     }
 }
 
-/// I²C commands sent to the sensor.
-#[derive(Debug, Copy, Clone)]
-enum Command {
-    /// Go into sleep mode.
-    Sleep,
-    /// Wake up from sleep mode.
-    WakeUp,
-    /// Measurement commands.
-    Measure {
-        power_mode: PowerMode,
-        order: MeasurementOrder,
-    },
-    /// Software reset.
-    SoftwareReset,
-    /// Read ID register.
-    ReadIdRegister,
-}
-
-impl Command {
-    fn as_bytes(self) -> [u8; 2] {
-        match self {
-            Command::Sleep => [0xB0, 0x98],
-            Command::WakeUp => [0x35, 0x17],
-            Command::Measure {
-                power_mode: PowerMode::NormalMode,
-                order: TemperatureFirst,
-            } => [0x78, 0x66],
-            Command::Measure {
-                power_mode: PowerMode::NormalMode,
-                order: HumidityFirst,
-            } => [0x58, 0xE0],
-            Command::Measure {
-                power_mode: PowerMode::LowPower,
-                order: TemperatureFirst,
-            } => [0x60, 0x9C],
-            Command::Measure {
-                power_mode: PowerMode::LowPower,
-                order: HumidityFirst,
-            } => [0x40, 0x1A],
-            Command::ReadIdRegister => [0xEF, 0xC8],
-            Command::SoftwareReset => [0x80, 0x5D],
-        }
+impl<E: i2c::Error> From<CrcError> for Error<E> {
+    fn from(_: CrcError) -> Self {
+        Error::Crc
     }
 }
 
@@ -305,10 +471,13 @@ pub trait MeasurementDuration {
 /// Type parameters for the different sensor classes.
 pub mod sensor_class {
     /// Type parameter: First generation SHT sensor (SHTC1, SHTW2).
+    #[derive(Debug)]
     pub struct Sht1Gen;
     /// Type parameter: Second generation SHT sensor (SHTC3).
+    #[derive(Debug)]
     pub struct Sht2Gen;
     /// Type parameter: Generic driver that should work with all SHTCx sensors.
+    #[derive(Debug)]
     pub struct ShtGeneric;
 }
 
@@ -331,6 +500,10 @@ pub struct ShtCx<S: ShtSensor, I2C> {
     i2c: I2C,
     /// The I²C device address.
     address: u8,
+    /// Statistics counters (zero-sized without the `statistics` feature).
+    counters: Counters,
+    /// Calibration applied to measurements.
+    calibration: CalibrationProfile,
 }
 
 /// ShtC1 sensor
@@ -341,11 +514,7 @@ pub type ShtC1<I2C> = ShtCx<sensor_class::Sht1Gen, I2C>;
 /// See [ShtCx](struct.ShtCx.html) for detailed documentation of the available
 /// methods.
 pub fn shtc1<I2C>(i2c: I2C) -> ShtC1<I2C> {
-    ShtCx {
-        sensor: PhantomData,
-        i2c,
-        address: 0x70,
-    }
+    ShtCx::new(i2c, 0x70)
 }
 
 /// ShtC3 sensor
@@ -356,11 +525,7 @@ pub type ShtC3<I2C> = ShtCx<sensor_class::Sht2Gen, I2C>;
 /// See [ShtCx](struct.ShtCx.html) for detailed documentation of the available
 /// methods.
 pub fn shtc3<I2C>(i2c: I2C) -> ShtC3<I2C> {
-    ShtCx {
-        sensor: PhantomData,
-        i2c,
-        address: 0x70,
-    }
+    ShtCx::new(i2c, 0x70)
 }
 
 /// ShtW2 sensor
@@ -376,11 +541,7 @@ pub type ShtW2<I2C> = ShtCx<sensor_class::Sht1Gen, I2C>;
 pub fn shtw2<I2C>(i2c: I2C, address: u8) -> ShtW2<I2C> {
     // Note: Internally, the SHTW2 is identical to the SHTC1, just with
     // different packaging.
-    ShtCx {
-        sensor: PhantomData,
-        i2c,
-        address,
-    }
+    ShtCx::new(i2c, address)
 }
 
 /// Create a new generic instance of the driver.
@@ -388,11 +549,7 @@ pub fn shtw2<I2C>(i2c: I2C, address: u8) -> ShtW2<I2C> {
 /// See [ShtCx](struct.ShtCx.html) for detailed documentation of the available
 /// methods.
 pub fn generic<I2C>(i2c: I2C, address: u8) -> ShtCx<sensor_class::ShtGeneric, I2C> {
-    ShtCx {
-        sensor: PhantomData,
-        i2c,
-        address,
-    }
+    ShtCx::new(i2c, address)
 }
 
 impl MeasurementDuration for sensor_class::Sht1Gen {
@@ -460,6 +617,18 @@ where
     S::max_measurement_duration(mode)
 }
 
+impl<S: ShtSensor, I2C> ShtCx<S, I2C> {
+    fn new(i2c: I2C, address: u8) -> Self {
+        ShtCx {
+            sensor: PhantomData,
+            i2c,
+            address,
+            counters: Default::default(),
+            calibration: CalibrationProfile::NONE,
+        }
+    }
+}
+
 /// General functions.
 impl<S, I2C> ShtCx<S, I2C>
 where
@@ -473,37 +642,65 @@ where
 
     /// Write an I²C command to the sensor.
     fn send_command(&mut self, command: Command) -> Result<(), Error<I2C::Error>> {
-        self.i2c
-            .write(self.address, &command.as_bytes())
-            .map_err(Error::I2c)
+        self.counters.command(&command);
+        let result = self.i2c.write(self.address, &command.as_bytes());
+        self.i2c_result(result)
     }
 
-    /// Iterate over the provided buffer and validate the CRC8 checksum.
-    ///
-    /// If the checksum is wrong, return `Error::Crc`.
+    /// Read the response of the sensor into the provided buffer.
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error<I2C::Error>> {
+        let result = self.i2c.read(self.address, buf);
+        self.i2c_result(result)
+    }
+
+    /// Read the result of a measurement into the provided buffer.
     ///
-    /// Note: This method will consider every third byte a checksum byte. If
-    /// the buffer size is not a multiple of 3, then not all data will be
-    /// validated.
-    fn validate_crc(&self, buf: &[u8]) -> Result<(), Error<I2C::Error>> {
-        for chunk in buf.chunks(3) {
-            if chunk.len() == 3 && crc8(&[chunk[0], chunk[1]]) != chunk[2] {
-                return Err(Error::Crc);
-            }
+    /// If `poll` is set, a missing acknowledge means that the measurement is
+    /// still in progress. It is counted as retry instead of an I²C error.
+    fn read_result(&mut self, buf: &mut [u8], poll: bool) -> Result<(), Error<I2C::Error>> {
+        if !poll {
+            return self.read(buf);
         }
-        Ok(())
+        self.i2c.read(self.address, buf).map_err(|e| {
+            match i2c::Error::kind(&e) {
+                i2c::ErrorKind::NoAcknowledge(_) => self.counters.retry(),
+                kind => self.counters.i2c_error(kind),
+            }
+            Error::I2c(e)
+        })
     }
 
-    /// Read data into the provided buffer and validate the CRC8 checksum.
-    ///
-    /// If the checksum is wrong, return `Error::Crc`.
-    ///
-    /// Note: This method will consider every third byte a checksum byte. If
-    /// the buffer size is not a multiple of 3, then not all data will be
-    /// validated.
-    fn read_with_crc(&mut self, buf: &mut [u8]) -> Result<(), Error<I2C::Error>> {
-        self.i2c.read(self.address, buf).map_err(Error::I2c)?;
-        self.validate_crc(buf)
+    /// Count an I²C error and convert it.
+    fn i2c_result<T>(&mut self, result: Result<T, I2C::Error>) -> Result<T, Error<I2C::Error>> {
+        result.map_err(|e| {
+            self.counters.i2c_error(i2c::Error::kind(&e));
+            Error::I2c(e)
+        })
+    }
+
+    /// Count a CRC error and convert it.
+    fn crc_result<T>(&mut self, result: Result<T, CrcError>) -> Result<T, Error<I2C::Error>> {
+        result.map_err(|e| {
+            self.counters.crc_error();
+            e.into()
+        })
+    }
+
+    /// Convert the result of a poll into an [`nb::Result`], reporting
+    /// [`WouldBlock`](nb::Error::WouldBlock) if the sensor did not
+    /// acknowledge the transaction.
+    fn nb_result<T>(result: Result<T, Error<I2C::Error>>) -> nb::Result<T, Error<I2C::Error>> {
+        result.map_err(|e| match e {
+            Error::I2c(ref i2c_error)
+                if matches!(
+                    i2c::Error::kind(i2c_error),
+                    i2c::ErrorKind::NoAcknowledge(_)
+                ) =>
+            {
+                nb::Error::WouldBlock
+            }
+            e => nb::Error::Other(e),
+        })
     }
 
     /// Return the raw ID register.
@@ -512,10 +709,9 @@ where
         self.send_command(Command::ReadIdRegister)?;
 
         // Read id register
-        let mut buf = [0; 3];
-        self.read_with_crc(&mut buf)?;
-
-        Ok(u16::from_be_bytes([buf[0], buf[1]]))
+        let mut buf = [0; protocol::ID_REGISTER_RESPONSE_LEN];
+        self.read(&mut buf)?;
+        self.crc_result(protocol::parse_id_register(&buf))
     }
 
     /// Return the 7-bit device identifier.
@@ -523,9 +719,7 @@ where
     /// Should be 0x47 (71) for the SHTC3 and 0x07 (7) for the SHTC1.
     pub fn device_identifier(&mut self) -> Result<u8, Error<I2C::Error>> {
         let ident = self.raw_id_register()?;
-        let lsb = (ident & 0b0011_1111) as u8;
-        let msb = ((ident & 0b0000_1000_0000_0000) >> 5) as u8;
-        Ok(lsb | msb)
+        Ok(protocol::device_identifier(ident))
     }
 
     /// Trigger a soft reset.
@@ -563,59 +757,199 @@ where
         self.send_command(Command::Measure { power_mode, order })
     }
 
+    /// Start a measurement and return a token for reading its result.
+    fn start_pending<T: MeasurementKind>(
+        &mut self,
+        power_mode: PowerMode,
+        order: MeasurementOrder,
+        now_us: u64,
+    ) -> Result<PendingMeasurement<T>, Error<I2C::Error>>
+    where
+        S: MeasurementDuration,
+    {
+        self.start_measure_partial(power_mode, order)?;
+        let duration_us = S::max_measurement_duration(power_mode);
+        Ok(PendingMeasurement::new(
+            power_mode,
+            order,
+            now_us,
+            duration_us,
+        ))
+    }
+
     /// Start a combined temperature / humidity measurement.
-    pub fn start_measurement(&mut self, mode: PowerMode) -> Result<(), Error<I2C::Error>> {
-        self.start_measure_partial(mode, MeasurementOrder::TemperatureFirst)
+    ///
+    /// `now_us` is the current time of a monotonic clock in microseconds. It
+    /// is used to determine when the result will be ready.
+    pub fn start_measurement(
+        &mut self,
+        mode: PowerMode,
+        now_us: u64,
+    ) -> Result<PendingMeasurement<Measurement>, Error<I2C::Error>>
+    where
+        S: MeasurementDuration,
+    {
+        self.start_pending(mode, MeasurementOrder::TemperatureFirst, now_us)
     }
 
     /// Start a temperature measurement.
+    ///
+    /// `now_us` is the current time of a monotonic clock in microseconds. It
+    /// is used to determine when the result will be ready.
     pub fn start_temperature_measurement(
         &mut self,
         mode: PowerMode,
-    ) -> Result<(), Error<I2C::Error>> {
-        self.start_measure_partial(mode, MeasurementOrder::TemperatureFirst)
+        now_us: u64,
+    ) -> Result<PendingMeasurement<Temperature>, Error<I2C::Error>>
+    where
+        S: MeasurementDuration,
+    {
+        self.start_pending(mode, MeasurementOrder::TemperatureFirst, now_us)
     }
 
     /// Start a humidity measurement.
-    pub fn start_humidity_measurement(&mut self, mode: PowerMode) -> Result<(), Error<I2C::Error>> {
-        self.start_measure_partial(mode, MeasurementOrder::HumidityFirst)
+    ///
+    /// `now_us` is the current time of a monotonic clock in microseconds. It
+    /// is used to determine when the result will be ready.
+    pub fn start_humidity_measurement(
+        &mut self,
+        mode: PowerMode,
+        now_us: u64,
+    ) -> Result<PendingMeasurement<Humidity>, Error<I2C::Error>>
+    where
+        S: MeasurementDuration,
+    {
+        self.start_pending(mode, MeasurementOrder::HumidityFirst, now_us)
     }
 
     /// Read the result of a temperature / humidity measurement.
     pub fn get_measurement_result(&mut self) -> Result<Measurement, Error<I2C::Error>> {
-        let raw = self.get_raw_measurement_result()?;
-        Ok(raw.into())
+        self.read_measurement(MeasurementOrder::TemperatureFirst, false)
     }
 
     /// Read the result of a temperature measurement.
     pub fn get_temperature_measurement_result(&mut self) -> Result<Temperature, Error<I2C::Error>> {
-        let raw = self.get_raw_partial_measurement_result()?;
-        Ok(Temperature::from_raw(raw))
+        self.read_temperature(false)
     }
 
     /// Read the result of a humidity measurement.
+    ///
+    /// If the [calibration](ShtCx::set_calibration) corrects the
+    /// temperature, the full response (humidity and temperature) is read, so
+    /// the humidity can be re-derived at the compensated temperature.
     pub fn get_humidity_measurement_result(&mut self) -> Result<Humidity, Error<I2C::Error>> {
-        let raw = self.get_raw_partial_measurement_result()?;
-        Ok(Humidity::from_raw(raw))
+        self.read_humidity(false)
     }
 
     /// Read the raw result of a combined temperature / humidity measurement.
     pub fn get_raw_measurement_result(&mut self) -> Result<RawMeasurement, Error<I2C::Error>> {
-        let mut buf = [0; 6];
-        self.read_with_crc(&mut buf)?;
-        Ok(RawMeasurement {
-            temperature: u16::from_be_bytes([buf[0], buf[1]]),
-            humidity: u16::from_be_bytes([buf[3], buf[4]]),
-        })
+        self.read_raw_measurement(MeasurementOrder::TemperatureFirst, false)
+    }
+
+    /// Read and calibrate the result of a combined measurement that was
+    /// requested with the given measurement order.
+    fn read_measurement(
+        &mut self,
+        order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<Measurement, Error<I2C::Error>> {
+        let raw = self.read_raw_measurement(order, poll)?;
+        Ok(self.calibration.apply(raw.into()))
+    }
+
+    /// Read and calibrate the result of a temperature measurement.
+    fn read_temperature(&mut self, poll: bool) -> Result<Temperature, Error<I2C::Error>> {
+        let raw = self.read_raw_partial_measurement(poll)?;
+        Ok(self
+            .calibration
+            .apply_temperature(Temperature::from_raw(raw)))
+    }
+
+    /// Read and calibrate the result of a humidity measurement.
+    fn read_humidity(&mut self, poll: bool) -> Result<Humidity, Error<I2C::Error>> {
+        if self.calibration.corrects_temperature() {
+            let measurement = self.read_measurement(MeasurementOrder::HumidityFirst, poll)?;
+            return Ok(measurement.humidity);
+        }
+        let raw = self.read_raw_partial_measurement(poll)?;
+        Ok(self.calibration.apply_humidity(Humidity::from_raw(raw)))
+    }
+
+    /// Read the result of a temperature / humidity measurement if it is
+    /// available.
+    ///
+    /// While the measurement is in progress, the sensor does not acknowledge
+    /// read requests. In that case, [`WouldBlock`](nb::Error::WouldBlock) is
+    /// returned instead of an I²C error, so the result can be polled without
+    /// waiting for the maximum measurement duration.
+    pub fn poll_measurement_result(&mut self) -> nb::Result<Measurement, Error<I2C::Error>> {
+        Self::nb_result(self.read_measurement(MeasurementOrder::TemperatureFirst, true))
+    }
+
+    /// Read the result of a temperature measurement if it is available.
+    ///
+    /// See [`poll_measurement_result`](ShtCx::poll_measurement_result) for
+    /// details.
+    pub fn poll_temperature_measurement_result(
+        &mut self,
+    ) -> nb::Result<Temperature, Error<I2C::Error>> {
+        Self::nb_result(self.read_temperature(true))
+    }
+
+    /// Read the result of a humidity measurement if it is available.
+    ///
+    /// See [`poll_measurement_result`](ShtCx::poll_measurement_result) for
+    /// details.
+    pub fn poll_humidity_measurement_result(&mut self) -> nb::Result<Humidity, Error<I2C::Error>> {
+        Self::nb_result(self.read_humidity(true))
+    }
+
+    /// Read the raw result of a combined temperature / humidity measurement
+    /// if it is available.
+    ///
+    /// See [`poll_measurement_result`](ShtCx::poll_measurement_result) for
+    /// details.
+    pub fn poll_raw_measurement_result(&mut self) -> nb::Result<RawMeasurement, Error<I2C::Error>> {
+        Self::nb_result(self.read_raw_measurement(MeasurementOrder::TemperatureFirst, true))
+    }
+
+    /// Read the raw result of a partial temperature or humidity measurement
+    /// if it is available.
+    ///
+    /// See [`poll_measurement_result`](ShtCx::poll_measurement_result) for
+    /// details.
+    pub fn poll_raw_partial_measurement_result(&mut self) -> nb::Result<u16, Error<I2C::Error>> {
+        Self::nb_result(self.read_raw_partial_measurement(true))
+    }
+
+    /// Read the raw result of a combined measurement that was requested with
+    /// the given measurement order.
+    fn read_raw_measurement(
+        &mut self,
+        order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<RawMeasurement, Error<I2C::Error>> {
+        let mut buf = [0; protocol::MEASUREMENT_RESPONSE_LEN];
+        self.read_result(&mut buf, poll)?;
+        let raw = self.crc_result(protocol::parse_raw_measurement(&buf, order))?;
+        self.counters.measurement();
+        Ok(raw)
     }
 
     /// Read the raw result of a partial temperature or humidity measurement.
     ///
-    /// Return the raw 3-byte buffer (after validating CRC).
+    /// Return the raw 16-bit value (after validating CRC).
     pub fn get_raw_partial_measurement_result(&mut self) -> Result<u16, Error<I2C::Error>> {
-        let mut buf = [0; 3];
-        self.read_with_crc(&mut buf)?;
-        Ok(u16::from_be_bytes([buf[0], buf[1]]))
+        self.read_raw_partial_measurement(false)
+    }
+
+    /// Read the raw result of a partial temperature or humidity measurement.
+    fn read_raw_partial_measurement(&mut self, poll: bool) -> Result<u16, Error<I2C::Error>> {
+        let mut buf = [0; protocol::PARTIAL_MEASUREMENT_RESPONSE_LEN];
+        self.read_result(&mut buf, poll)?;
+        let raw = self.crc_result(protocol::parse_raw_partial_measurement(&buf))?;
+        self.counters.measurement();
+        Ok(raw)
     }
 }
 
@@ -638,7 +972,7 @@ where
         mode: PowerMode,
         delay: &mut impl DelayNs,
     ) -> Result<Measurement, Error<I2C::Error>> {
-        self.start_measurement(mode)?;
+        self.start_measure_partial(mode, MeasurementOrder::TemperatureFirst)?;
         self.wait_for_measurement(mode, delay);
         self.get_measurement_result()
     }
@@ -654,7 +988,7 @@ where
         mode: PowerMode,
         delay: &mut impl DelayNs,
     ) -> Result<Temperature, Error<I2C::Error>> {
-        self.start_temperature_measurement(mode)?;
+        self.start_measure_partial(mode, MeasurementOrder::TemperatureFirst)?;
         self.wait_for_measurement(mode, delay);
         self.get_temperature_measurement_result()
     }
@@ -664,13 +998,16 @@ where
     /// This is a blocking function call.
     ///
     /// Internally, it will request a measurement in "humidity first" mode
-    /// and only read the first half of the measurement response.
+    /// and only read the first half of the measurement response. If the
+    /// [calibration](ShtCx::set_calibration) corrects the temperature, the
+    /// temperature is read as well to re-derive the humidity at the
+    /// compensated temperature.
     pub fn measure_humidity(
         &mut self,
         mode: PowerMode,
         delay: &mut impl DelayNs,
     ) -> Result<Humidity, Error<I2C::Error>> {
-        self.start_humidity_measurement(mode)?;
+        self.start_measure_partial(mode, MeasurementOrder::HumidityFirst)?;
         self.wait_for_measurement(mode, delay);
         self.get_humidity_measurement_result()
     }
@@ -732,6 +1069,7 @@ impl_low_power!(sensor_class::ShtGeneric);
 mod tests {
     use super::*;
 
+    use crate::crc::crc8;
     use embedded_hal::i2c::ErrorKind;
     use embedded_hal_mock::eh1::{
         delay::NoopDelay,
@@ -754,67 +1092,6 @@ mod tests {
             assert_eq!(err, Error::I2c(ErrorKind::Other));
             sht.destroy().done();
         }
-
-        /// Test the `validate_crc` function.
-        #[test]
-        fn validate_crc() {
-            let mock = I2cMock::new(&[]);
-            let sht = shtc3(mock);
-
-            // Not enough data
-            sht.validate_crc(&[]).unwrap();
-            sht.validate_crc(&[0xbe]).unwrap();
-            sht.validate_crc(&[0xbe, 0xef]).unwrap();
-
-            // Valid CRC
-            sht.validate_crc(&[0xbe, 0xef, 0x92]).unwrap();
-
-            // Invalid CRC
-            match sht.validate_crc(&[0xbe, 0xef, 0x91]) {
-                Err(Error::Crc) => {}
-                Err(_) => panic!("Invalid error: Must be Crc"),
-                Ok(_) => panic!("CRC check did not fail"),
-            }
-
-            // Valid CRC (8 bytes)
-            sht.validate_crc(&[0xbe, 0xef, 0x92, 0xbe, 0xef, 0x92, 0x00, 0x00])
-                .unwrap();
-
-            // Invalid CRC (8 bytes)
-            match sht.validate_crc(&[0xbe, 0xef, 0x92, 0xbe, 0xef, 0xff, 0x00, 0x00]) {
-                Err(Error::Crc) => {}
-                Err(_) => panic!("Invalid error: Must be Crc"),
-                Ok(_) => panic!("CRC check did not fail"),
-            }
-
-            sht.destroy().done();
-        }
-
-        /// Test the `read_with_crc` function.
-        #[test]
-        fn read_with_crc() {
-            let mut buf = [0; 3];
-
-            // Valid CRC
-            let expectations = [Transaction::read(SHT_ADDR, vec![0xbe, 0xef, 0x92])];
-            let mock = I2cMock::new(&expectations);
-            let mut sht = shtc3(mock);
-            sht.read_with_crc(&mut buf).unwrap();
-            assert_eq!(buf, [0xbe, 0xef, 0x92]);
-            sht.destroy().done();
-
-            // Invalid CRC
-            let expectations = [Transaction::read(SHT_ADDR, vec![0xbe, 0xef, 0x00])];
-            let mock = I2cMock::new(&expectations);
-            let mut sht = shtc3(mock);
-            match sht.read_with_crc(&mut buf) {
-                Err(Error::Crc) => {}
-                Err(_) => panic!("Invalid error: Must be Crc"),
-                Ok(_) => panic!("CRC check did not fail"),
-            }
-            assert_eq!(buf, [0xbe, 0xef, 0x00]); // Buf was changed
-            sht.destroy().done();
-        }
     }
 
     mod factory_functions {
@@ -992,6 +1269,141 @@ mod tests {
             sht.destroy().done();
         }
 
+        #[test]
+        fn pending_measurement() {
+            let expectations = [
+                Transaction::write(SHT_ADDR, vec![0x60, 0x9C]),
+                Transaction::read(
+                    SHT_ADDR,
+                    vec![
+                        0b0110_0100,
+                        0b1000_1011,
+                        0b1100_0111,
+                        0b1010_0001,
+                        0b0011_0011,
+                        0b0001_1100,
+                    ],
+                ),
+            ];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc3(mock);
+            let pending = sht.start_measurement(PowerMode::LowPower, 1_000).unwrap();
+            assert_eq!(pending.power_mode(), PowerMode::LowPower);
+            assert_eq!(pending.order(), MeasurementOrder::TemperatureFirst);
+            assert_eq!(pending.deadline_us(), 1_800);
+            assert!(!pending.is_ready(1_799));
+            assert_eq!(pending.remaining_us(1_700), 100);
+            assert!(pending.is_ready(1_800));
+            assert_eq!(pending.remaining_us(2_000), 0);
+            let measurement: Measurement = pending.finish(&mut sht).unwrap();
+            assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+            assert_eq!(measurement.humidity.as_millipercent(), 62_968);
+            sht.destroy().done();
+        }
+
+        #[test]
+        fn pending_partial_measurements() {
+            let expectations = [
+                Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+                Transaction::read(SHT_ADDR, vec![0b0110_0100, 0b1000_1011, 0b1100_0111]),
+                Transaction::write(SHT_ADDR, vec![0x58, 0xE0]),
+                Transaction::read(SHT_ADDR, vec![0b1010_0001, 0b0011_0011, 0b0001_1100]),
+            ];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc1(mock);
+
+            let pending = sht
+                .start_temperature_measurement(PowerMode::NormalMode, 0)
+                .unwrap();
+            assert_eq!(pending.deadline_us(), 14_400);
+            let temperature: Temperature = pending.finish(&mut sht).unwrap();
+            assert_eq!(temperature.as_millidegrees_celsius(), 23_730);
+
+            let pending = sht
+                .start_humidity_measurement(PowerMode::NormalMode, 0)
+                .unwrap();
+            assert_eq!(pending.order(), MeasurementOrder::HumidityFirst);
+            let humidity: Humidity = pending.finish(&mut sht).unwrap();
+            assert_eq!(humidity.as_millipercent(), 62_968);
+
+            sht.destroy().done();
+        }
+
+        /// Polling reports `WouldBlock` while the sensor NACKs the read.
+        #[test]
+        fn poll_measurement_result() {
+            let expectations = [
+                Transaction::read(SHT_ADDR, vec![0; 6])
+                    .with_error(ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address)),
+                Transaction::read(SHT_ADDR, vec![0; 3])
+                    .with_error(ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Unknown)),
+                Transaction::read(
+                    SHT_ADDR,
+                    vec![
+                        0b0110_0100,
+                        0b1000_1011,
+                        0b1100_0111,
+                        0b1010_0001,
+                        0b0011_0011,
+                        0b0001_1100,
+                    ],
+                ),
+                Transaction::read(SHT_ADDR, vec![0b1010_0001, 0b0011_0011, 0b0001_1100]),
+                Transaction::read(SHT_ADDR, vec![0b0110_0100, 0b1000_1011, 0b1100_0111]),
+            ];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc3(mock);
+            assert_eq!(sht.poll_measurement_result(), Err(nb::Error::WouldBlock));
+            assert_eq!(
+                sht.poll_temperature_measurement_result(),
+                Err(nb::Error::WouldBlock)
+            );
+            let measurement = sht.poll_measurement_result().unwrap();
+            assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+            let humidity = sht.poll_humidity_measurement_result().unwrap();
+            assert_eq!(humidity.as_millipercent(), 62_968);
+            assert_eq!(sht.poll_raw_partial_measurement_result(), Ok(0x648b));
+            sht.destroy().done();
+        }
+
+        /// Polling forwards errors other than NACKs.
+        #[test]
+        fn poll_measurement_result_errors() {
+            let expectations = [
+                Transaction::read(SHT_ADDR, vec![0; 6]).with_error(ErrorKind::Bus),
+                Transaction::read(SHT_ADDR, vec![0; 6]),
+            ];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc3(mock);
+            assert_eq!(
+                sht.poll_raw_measurement_result(),
+                Err(nb::Error::Other(Error::I2c(ErrorKind::Bus)))
+            );
+            assert_eq!(
+                sht.poll_raw_measurement_result(),
+                Err(nb::Error::Other(Error::Crc))
+            );
+            sht.destroy().done();
+        }
+
+        /// Ensure that invalid CRCs are detected when reading results.
+        #[test]
+        fn partial_measurement_crc_error() {
+            // Valid CRC
+            let expectations = [Transaction::read(SHT_ADDR, vec![0xbe, 0xef, 0x92])];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc3(mock);
+            assert_eq!(sht.get_raw_partial_measurement_result(), Ok(0xbeef));
+            sht.destroy().done();
+
+            // Invalid CRC
+            let expectations = [Transaction::read(SHT_ADDR, vec![0xbe, 0xef, 0x00])];
+            let mock = I2cMock::new(&expectations);
+            let mut sht = shtc3(mock);
+            assert_eq!(sht.get_raw_partial_measurement_result(), Err(Error::Crc));
+            sht.destroy().done();
+        }
+
         /// Ensure that I²C write errors are handled when measuring.
         #[test]
         fn measure_write_error() {
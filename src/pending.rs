@@ -0,0 +1,187 @@
+use core::marker::PhantomData;
+
+use embedded_hal::i2c::{I2c, SevenBitAddress};
+
+use crate::{
+    Error, Humidity, Measurement, MeasurementOrder, PowerMode, ShtCx, ShtSensor, Temperature,
+};
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for crate::Measurement {}
+    impl Sealed for crate::Temperature {}
+    impl Sealed for crate::Humidity {}
+}
+
+/// The kind of result a [`PendingMeasurement`] yields: [`Measurement`],
+/// [`Temperature`] or [`Humidity`].
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait MeasurementKind: private::Sealed + Sized {
+    /// Read the measurement result from the sensor.
+    ///
+    /// If `poll` is set, a missing acknowledge is counted as retry.
+    #[doc(hidden)]
+    fn read<S, I2C>(
+        sht: &mut ShtCx<S, I2C>,
+        order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<Self, Error<I2C::Error>>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>;
+}
+
+impl MeasurementKind for Measurement {
+    fn read<S, I2C>(
+        sht: &mut ShtCx<S, I2C>,
+        order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<Self, Error<I2C::Error>>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>,
+    {
+        sht.read_measurement(order, poll)
+    }
+}
+
+impl MeasurementKind for Temperature {
+    fn read<S, I2C>(
+        sht: &mut ShtCx<S, I2C>,
+        _order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<Self, Error<I2C::Error>>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>,
+    {
+        sht.read_temperature(poll)
+    }
+}
+
+impl MeasurementKind for Humidity {
+    fn read<S, I2C>(
+        sht: &mut ShtCx<S, I2C>,
+        _order: MeasurementOrder,
+        poll: bool,
+    ) -> Result<Self, Error<I2C::Error>>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>,
+    {
+        sht.read_humidity(poll)
+    }
+}
+
+/// Result of [`PendingMeasurement::poll`]: The measurement result, or the
+/// token together with the error.
+pub type PollResult<T, E> = Result<T, (PendingMeasurement<T>, nb::Error<Error<E>>)>;
+
+/// A measurement that was started, but whose result hasn't been read yet.
+///
+/// Returned by the non-blocking `start_*` methods of [`ShtCx`]. The token
+/// remembers the power mode and measurement order and calculates the time at
+/// which the result will be available (based on the maximum measurement
+/// duration of the sensor). [`finish`](PendingMeasurement::finish) only
+/// yields the kind of result `T` that was started.
+///
+/// Timestamps are in microseconds and may come from any monotonic clock, as
+/// long as the same clock is used for starting the measurement and for
+/// checking whether it is ready.
+///
+/// The token can't be copied or cloned and is consumed by
+/// [`finish`] and by a successful [`poll`], so the result of every started
+/// measurement is read at most once.
+///
+/// The untyped methods [`get_measurement_result`],
+/// [`get_temperature_measurement_result`] and
+/// [`get_humidity_measurement_result`] (and their `poll_*` variants) remain
+/// available. They read the same results as [`finish`] for a measurement
+/// started with `start_measurement`, `start_temperature_measurement` and
+/// `start_humidity_measurement` respectively, but they don't check that a
+/// measurement of that kind was started, or that it is ready.
+///
+/// [`get_measurement_result`]: ShtCx::get_measurement_result
+/// [`get_temperature_measurement_result`]: ShtCx::get_temperature_measurement_result
+/// [`get_humidity_measurement_result`]: ShtCx::get_humidity_measurement_result
+/// [`finish`]: PendingMeasurement::finish
+/// [`poll`]: PendingMeasurement::poll
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "the measurement result must be read with `finish` or `poll`"]
+pub struct PendingMeasurement<T> {
+    power_mode: PowerMode,
+    order: MeasurementOrder,
+    deadline_us: u64,
+    kind: PhantomData<T>,
+}
+
+impl<T: MeasurementKind> PendingMeasurement<T> {
+    pub(crate) fn new(
+        power_mode: PowerMode,
+        order: MeasurementOrder,
+        started_us: u64,
+        duration_us: u32,
+    ) -> Self {
+        Self {
+            power_mode,
+            order,
+            deadline_us: started_us.saturating_add(u64::from(duration_us)),
+            kind: PhantomData,
+        }
+    }
+
+    /// Return the power mode of the measurement.
+    pub fn power_mode(&self) -> PowerMode {
+        self.power_mode
+    }
+
+    /// Return the measurement order that was requested.
+    pub fn order(&self) -> MeasurementOrder {
+        self.order
+    }
+
+    /// Return the timestamp (in microseconds) at which the result will be
+    /// available.
+    pub fn deadline_us(&self) -> u64 {
+        self.deadline_us
+    }
+
+    /// Return whether the measurement result is available at `now_us`.
+    pub fn is_ready(&self, now_us: u64) -> bool {
+        now_us >= self.deadline_us
+    }
+
+    /// Return the remaining time (in microseconds) until the result is
+    /// available at `now_us`.
+    pub fn remaining_us(&self, now_us: u64) -> u64 {
+        self.deadline_us.saturating_sub(now_us)
+    }
+
+    /// Read the measurement result if it is available.
+    ///
+    /// The token is consumed by a successful read. On failure it is returned
+    /// together with the error: [`WouldBlock`](nb::Error::WouldBlock) while
+    /// the sensor does not acknowledge the read because the measurement is
+    /// still in progress, so the token can be polled again later.
+    pub fn poll<S, I2C>(self, sht: &mut ShtCx<S, I2C>) -> PollResult<T, I2C::Error>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>,
+    {
+        ShtCx::<S, I2C>::nb_result(T::read(sht, self.order, true)).map_err(|e| (self, e))
+    }
+
+    /// Read the measurement result.
+    ///
+    /// Make sure that the measurement [is ready](PendingMeasurement::is_ready)
+    /// before calling this, otherwise the sensor will not acknowledge the read
+    /// and an I²C error is returned.
+    pub fn finish<S, I2C>(self, sht: &mut ShtCx<S, I2C>) -> Result<T, Error<I2C::Error>>
+    where
+        S: ShtSensor,
+        I2C: I2c<SevenBitAddress>,
+    {
+        T::read(sht, self.order, false)
+    }
+}
@@ -0,0 +1,263 @@
+use embedded_hal::{
+    delay::DelayNs,
+    digital::OutputPin,
+    i2c::{self, I2c, SevenBitAddress},
+};
+
+use crate::{
+    Error, Humidity, Measurement, MeasurementDuration, PowerMode, ShtCx, ShtSensor, Temperature,
+};
+
+/// All possible errors of the [`PowerGated`] driver.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PowerGateError<E: i2c::Error, PE> {
+    /// Sensor communication error
+    Sensor(Error<E>),
+    /// Error while switching the supply pin
+    Pin(PE),
+}
+
+impl<E, PE> From<Error<E>> for PowerGateError<E, PE>
+where
+    E: i2c::Error,
+{
+    fn from(e: Error<E>) -> Self {
+        PowerGateError::Sensor(e)
+    }
+}
+
+/// Driver for a sensor that is powered from a GPIO pin.
+///
+/// This is useful for sensors without sleep mode (e.g. the SHTC1 and SHTW2)
+/// in battery powered applications: The sensor is only powered while a
+/// measurement is running. If the sensor stops responding, power cycling it
+/// with [`recover`](PowerGated::recover) can be used as a last resort.
+///
+/// Note: While the sensor is not powered, the I²C lines should not be pulled
+/// up, otherwise the sensor may be powered through them.
+///
+/// Create an instance with [`ShtCx::into_power_gated`]. The supply pin is
+/// assumed to be low (sensor unpowered) at that point.
+#[derive(Debug)]
+pub struct PowerGated<S: ShtSensor, I2C, P> {
+    sht: ShtCx<S, I2C>,
+    pin: P,
+    powered: bool,
+}
+
+impl<S, I2C> ShtCx<S, I2C>
+where
+    S: ShtSensor,
+    I2C: I2c<SevenBitAddress>,
+{
+    /// Control the power supply of the sensor through the given pin.
+    pub fn into_power_gated<P: OutputPin>(self, pin: P) -> PowerGated<S, I2C, P> {
+        PowerGated {
+            sht: self,
+            pin,
+            powered: false,
+        }
+    }
+}
+
+impl<S, I2C, P> PowerGated<S, I2C, P>
+where
+    S: ShtSensor + MeasurementDuration,
+    I2C: I2c<SevenBitAddress>,
+    P: OutputPin,
+{
+    /// Time the sensor needs until it is ready after power-up.
+    ///
+    /// The maximum power-up time of all supported sensors (1 ms for the
+    /// SHTC1/SHTW2 according to the datasheet).
+    pub const POWER_UP_TIME_US: u32 = 1_000;
+
+    /// Time the supply is switched off when power cycling the sensor.
+    ///
+    /// Depending on the decoupling capacitance, it may take a while until the
+    /// supply voltage has dropped far enough for the sensor to reset.
+    pub const POWER_OFF_TIME_US: u32 = 10_000;
+
+    /// Destroy the driver instance, return the driver and the supply pin.
+    ///
+    /// The sensor is left in its current power state (see
+    /// [`is_powered`](PowerGated::is_powered)).
+    pub fn into_parts(self) -> (ShtCx<S, I2C>, P) {
+        (self.sht, self.pin)
+    }
+
+    /// Return whether the sensor is currently powered.
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Return the driver of the sensor, e.g. for sending commands while the
+    /// sensor is powered.
+    pub fn sensor(&mut self) -> &mut ShtCx<S, I2C> {
+        &mut self.sht
+    }
+
+    /// Power up the sensor and wait until it is ready.
+    pub fn power_up(
+        &mut self,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), PowerGateError<I2C::Error, P::Error>> {
+        if !self.powered {
+            self.pin.set_high().map_err(PowerGateError::Pin)?;
+            self.powered = true;
+            delay.delay_us(Self::POWER_UP_TIME_US);
+        }
+        Ok(())
+    }
+
+    /// Cut the power supply of the sensor.
+    pub fn power_down(&mut self) -> Result<(), PowerGateError<I2C::Error, P::Error>> {
+        self.pin.set_low().map_err(PowerGateError::Pin)?;
+        self.powered = false;
+        Ok(())
+    }
+
+    /// Power cycle the sensor and check whether it responds again.
+    ///
+    /// This is the last resort when the sensor doesn't respond anymore. The
+    /// sensor is left powered and its device identifier is returned.
+    pub fn recover(
+        &mut self,
+        delay: &mut impl DelayNs,
+    ) -> Result<u8, PowerGateError<I2C::Error, P::Error>> {
+        self.power_down()?;
+        delay.delay_us(Self::POWER_OFF_TIME_US);
+        self.power_up(delay)?;
+        Ok(self.sht.device_identifier()?)
+    }
+
+    /// Power up the sensor, run a command and power it down again.
+    fn powered<D, T>(
+        &mut self,
+        delay: &mut D,
+        command: impl FnOnce(&mut ShtCx<S, I2C>, &mut D) -> Result<T, Error<I2C::Error>>,
+    ) -> Result<T, PowerGateError<I2C::Error, P::Error>>
+    where
+        D: DelayNs,
+    {
+        self.power_up(delay)?;
+        let result = command(&mut self.sht, delay);
+        let powered_down = self.power_down();
+        let value = result?;
+        powered_down?;
+        Ok(value)
+    }
+
+    /// Power up the sensor, run a temperature/humidity measurement and power
+    /// the sensor down again.
+    pub fn measure(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Measurement, PowerGateError<I2C::Error, P::Error>> {
+        self.powered(delay, |sht, delay| sht.measure(mode, delay))
+    }
+
+    /// Power up the sensor, run a temperature measurement and power the
+    /// sensor down again.
+    pub fn measure_temperature(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Temperature, PowerGateError<I2C::Error, P::Error>> {
+        self.powered(delay, |sht, delay| sht.measure_temperature(mode, delay))
+    }
+
+    /// Power up the sensor, run a humidity measurement and power the sensor
+    /// down again.
+    pub fn measure_humidity(
+        &mut self,
+        mode: PowerMode,
+        delay: &mut impl DelayNs,
+    ) -> Result<Humidity, PowerGateError<I2C::Error, P::Error>> {
+        self.powered(delay, |sht, delay| sht.measure_humidity(mode, delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+        pin::{Mock as PinMock, State, Transaction as PinTransaction},
+    };
+
+    use crate::shtc1;
+
+    const SHT_ADDR: u8 = 0x70;
+
+    #[test]
+    fn measure() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            Transaction::write(SHT_ADDR, vec![0x58, 0xe0]),
+            Transaction::read(SHT_ADDR, vec![0xa1, 0x33, 0x1c]),
+        ];
+        let pin = PinMock::new(&[
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+        ]);
+        let mut sht = shtc1(I2cMock::new(&expectations)).into_power_gated(pin);
+        assert!(!sht.is_powered());
+        let measurement = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+        assert!(!sht.is_powered());
+        let humidity = sht
+            .measure_humidity(PowerMode::NormalMode, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(humidity.as_millipercent(), 62_968);
+        let (sht, mut pin) = sht.into_parts();
+        sht.destroy().done();
+        pin.done();
+    }
+
+    /// The sensor is powered down even if the measurement fails.
+    #[test]
+    fn measure_error() {
+        let expectations =
+            [Transaction::write(SHT_ADDR, vec![0x78, 0x66]).with_error(ErrorKind::Other)];
+        let pin = PinMock::new(&[
+            PinTransaction::set(State::High),
+            PinTransaction::set(State::Low),
+        ]);
+        let mut sht = shtc1(I2cMock::new(&expectations)).into_power_gated(pin);
+        let err = sht
+            .measure_temperature(PowerMode::NormalMode, &mut NoopDelay)
+            .unwrap_err();
+        assert_eq!(err, PowerGateError::Sensor(Error::I2c(ErrorKind::Other)));
+        assert!(!sht.is_powered());
+        let (sht, mut pin) = sht.into_parts();
+        sht.destroy().done();
+        pin.done();
+    }
+
+    #[test]
+    fn recover() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0xef, 0xc8]),
+            // SHTC1 ID register
+            Transaction::read(SHT_ADDR, vec![0x00, 0x07, 0x16]),
+        ];
+        let pin = PinMock::new(&[
+            PinTransaction::set(State::Low),
+            PinTransaction::set(State::High),
+        ]);
+        let mut sht = shtc1(I2cMock::new(&expectations)).into_power_gated(pin);
+        assert_eq!(sht.recover(&mut NoopDelay).unwrap(), 0x07);
+        assert!(sht.is_powered());
+        let (sht, mut pin) = sht.into_parts();
+        sht.destroy().done();
+        pin.done();
+    }
+}
@@ -0,0 +1,276 @@
+//! Sans-IO implementation of the SHTCx protocol.
+//!
+//! This module contains the protocol logic of the driver without any I/O: It
+//! produces the command bytes for each operation and parses and validates the
+//! response buffers. This allows using the sensor with I²C stacks that don't
+//! provide a blocking or async [`I2c`](embedded_hal::i2c::I2c) implementation
+//! (e.g. DMA driven ones). The [`ShtCx`](crate::ShtCx) driver is built on top
+//! of this module.
+//!
+//! ```
+//! use shtcx::{protocol::{self, Command, MeasurementOrder}, PowerMode};
+//!
+//! let command = Command::Measure {
+//!     power_mode: PowerMode::NormalMode,
+//!     order: MeasurementOrder::TemperatureFirst,
+//! };
+//! assert_eq!(command.as_bytes(), [0x78, 0x66]);
+//!
+//! // Write the command to the sensor, wait for the measurement to finish and
+//! // read 6 bytes of response.
+//! let response = [0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c];
+//! let measurement =
+//!     protocol::parse_measurement(&response, MeasurementOrder::TemperatureFirst).unwrap();
+//! assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+//! ```
+
+use crate::{crc::crc8, Humidity, Measurement, PowerMode, RawMeasurement, Temperature};
+
+/// Length of the response to a combined temperature / humidity measurement.
+pub const MEASUREMENT_RESPONSE_LEN: usize = 6;
+
+/// Length of the response to a partial (temperature or humidity only)
+/// measurement.
+pub const PARTIAL_MEASUREMENT_RESPONSE_LEN: usize = 3;
+
+/// Length of the response to a [`Command::ReadIdRegister`] command.
+pub const ID_REGISTER_RESPONSE_LEN: usize = 3;
+
+/// Whether temperature or humidity is returned first when doing a measurement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MeasurementOrder {
+    /// Temperature first, then humidity.
+    TemperatureFirst,
+    /// Humidity first, then temperature.
+    HumidityFirst,
+}
+use MeasurementOrder::*;
+
+/// I²C commands sent to the sensor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Go into sleep mode.
+    Sleep,
+    /// Wake up from sleep mode.
+    WakeUp,
+    /// Measurement commands.
+    Measure {
+        /// The measurement power mode.
+        power_mode: PowerMode,
+        /// The order of the measurement results.
+        order: MeasurementOrder,
+    },
+    /// Software reset.
+    SoftwareReset,
+    /// Read ID register.
+    ReadIdRegister,
+}
+
+impl Command {
+    /// Return the bytes that need to be written to the sensor.
+    pub fn as_bytes(self) -> [u8; 2] {
+        match self {
+            Command::Sleep => [0xB0, 0x98],
+            Command::WakeUp => [0x35, 0x17],
+            Command::Measure {
+                power_mode: PowerMode::NormalMode,
+                order: TemperatureFirst,
+            } => [0x78, 0x66],
+            Command::Measure {
+                power_mode: PowerMode::NormalMode,
+                order: HumidityFirst,
+            } => [0x58, 0xE0],
+            Command::Measure {
+                power_mode: PowerMode::LowPower,
+                order: TemperatureFirst,
+            } => [0x60, 0x9C],
+            Command::Measure {
+                power_mode: PowerMode::LowPower,
+                order: HumidityFirst,
+            } => [0x40, 0x1A],
+            Command::ReadIdRegister => [0xEF, 0xC8],
+            Command::SoftwareReset => [0x80, 0x5D],
+        }
+    }
+}
+
+/// CRC checksum validation failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CrcError;
+
+/// Iterate over the provided buffer and validate the CRC8 checksum.
+///
+/// Note: This function will consider every third byte a checksum byte. If
+/// the buffer size is not a multiple of 3, then not all data will be
+/// validated.
+pub fn validate_crc(buf: &[u8]) -> Result<(), CrcError> {
+    for chunk in buf.chunks(3) {
+        if chunk.len() == 3 && crc8(&[chunk[0], chunk[1]]) != chunk[2] {
+            return Err(CrcError);
+        }
+    }
+    Ok(())
+}
+
+/// Validate and parse a 3-byte word (2 data bytes plus CRC).
+fn parse_word(buf: &[u8; 3]) -> Result<u16, CrcError> {
+    validate_crc(buf)?;
+    Ok(u16::from_be_bytes([buf[0], buf[1]]))
+}
+
+/// Parse the raw result of a combined temperature / humidity measurement
+/// requested with the given measurement order.
+pub fn parse_raw_measurement(
+    buf: &[u8; MEASUREMENT_RESPONSE_LEN],
+    order: MeasurementOrder,
+) -> Result<RawMeasurement, CrcError> {
+    validate_crc(buf)?;
+    let first = u16::from_be_bytes([buf[0], buf[1]]);
+    let second = u16::from_be_bytes([buf[3], buf[4]]);
+    let (temperature, humidity) = match order {
+        TemperatureFirst => (first, second),
+        HumidityFirst => (second, first),
+    };
+    Ok(RawMeasurement {
+        temperature,
+        humidity,
+    })
+}
+
+/// Parse the result of a combined temperature / humidity measurement
+/// requested with the given measurement order.
+pub fn parse_measurement(
+    buf: &[u8; MEASUREMENT_RESPONSE_LEN],
+    order: MeasurementOrder,
+) -> Result<Measurement, CrcError> {
+    parse_raw_measurement(buf, order).map(Measurement::from)
+}
+
+/// Parse the raw result of a partial temperature or humidity measurement.
+pub fn parse_raw_partial_measurement(
+    buf: &[u8; PARTIAL_MEASUREMENT_RESPONSE_LEN],
+) -> Result<u16, CrcError> {
+    parse_word(buf)
+}
+
+/// Parse the result of a temperature measurement (requested with
+/// [`MeasurementOrder::TemperatureFirst`]).
+pub fn parse_temperature(
+    buf: &[u8; PARTIAL_MEASUREMENT_RESPONSE_LEN],
+) -> Result<Temperature, CrcError> {
+    parse_word(buf).map(Temperature::from_raw)
+}
+
+/// Parse the result of a humidity measurement (requested with
+/// [`MeasurementOrder::HumidityFirst`]).
+pub fn parse_humidity(buf: &[u8; PARTIAL_MEASUREMENT_RESPONSE_LEN]) -> Result<Humidity, CrcError> {
+    parse_word(buf).map(Humidity::from_raw)
+}
+
+/// Parse the response to a [`Command::ReadIdRegister`] command.
+pub fn parse_id_register(buf: &[u8; ID_REGISTER_RESPONSE_LEN]) -> Result<u16, CrcError> {
+    parse_word(buf)
+}
+
+/// Extract the 7-bit device identifier from the raw ID register.
+///
+/// Should be 0x47 (71) for the SHTC3 and 0x07 (7) for the SHTC1.
+pub fn device_identifier(id_register: u16) -> u8 {
+    let lsb = (id_register & 0b0011_1111) as u8;
+    let msb = ((id_register & 0b0000_1000_0000_0000) >> 5) as u8;
+    lsb | msb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Measurement result from the datasheet (section 5.4).
+    const RESULT: [u8; 6] = [0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c];
+
+    /// Test the `validate_crc` function.
+    #[test]
+    fn validate_crc() {
+        // Not enough data
+        super::validate_crc(&[]).unwrap();
+        super::validate_crc(&[0xbe]).unwrap();
+        super::validate_crc(&[0xbe, 0xef]).unwrap();
+
+        // Valid CRC
+        super::validate_crc(&[0xbe, 0xef, 0x92]).unwrap();
+
+        // Invalid CRC
+        assert_eq!(super::validate_crc(&[0xbe, 0xef, 0x91]), Err(CrcError));
+
+        // Valid CRC (8 bytes)
+        super::validate_crc(&[0xbe, 0xef, 0x92, 0xbe, 0xef, 0x92, 0x00, 0x00]).unwrap();
+
+        // Invalid CRC (8 bytes)
+        assert_eq!(
+            super::validate_crc(&[0xbe, 0xef, 0x92, 0xbe, 0xef, 0xff, 0x00, 0x00]),
+            Err(CrcError)
+        );
+    }
+
+    #[test]
+    fn command_bytes() {
+        let measure = |power_mode, order| Command::Measure { power_mode, order }.as_bytes();
+        assert_eq!(Command::Sleep.as_bytes(), [0xb0, 0x98]);
+        assert_eq!(Command::WakeUp.as_bytes(), [0x35, 0x17]);
+        assert_eq!(Command::SoftwareReset.as_bytes(), [0x80, 0x5d]);
+        assert_eq!(Command::ReadIdRegister.as_bytes(), [0xef, 0xc8]);
+        assert_eq!(
+            measure(PowerMode::NormalMode, TemperatureFirst),
+            [0x78, 0x66]
+        );
+        assert_eq!(measure(PowerMode::NormalMode, HumidityFirst), [0x58, 0xe0]);
+        assert_eq!(measure(PowerMode::LowPower, TemperatureFirst), [0x60, 0x9c]);
+        assert_eq!(measure(PowerMode::LowPower, HumidityFirst), [0x40, 0x1a]);
+    }
+
+    #[test]
+    fn parse_measurement_orders() {
+        let raw = parse_raw_measurement(&RESULT, TemperatureFirst).unwrap();
+        assert_eq!(raw.temperature, 0x648b);
+        assert_eq!(raw.humidity, 0xa133);
+
+        let raw = parse_raw_measurement(&RESULT, HumidityFirst).unwrap();
+        assert_eq!(raw.temperature, 0xa133);
+        assert_eq!(raw.humidity, 0x648b);
+
+        let measurement = parse_measurement(&RESULT, TemperatureFirst).unwrap();
+        assert_eq!(measurement.temperature.as_millidegrees_celsius(), 23_730);
+        assert_eq!(measurement.humidity.as_millipercent(), 62_968);
+    }
+
+    #[test]
+    fn parse_crc_errors() {
+        let mut buf = RESULT;
+        buf[5] ^= 0x01;
+        assert_eq!(parse_measurement(&buf, TemperatureFirst), Err(CrcError));
+        assert_eq!(parse_temperature(&[0x64, 0x8b, 0x00]), Err(CrcError));
+        assert_eq!(parse_humidity(&[0xa1, 0x33, 0x00]), Err(CrcError));
+        assert_eq!(parse_id_register(&[0x08, 0x07, 0x00]), Err(CrcError));
+    }
+
+    #[test]
+    fn parse_partial() {
+        let temperature = parse_temperature(&[0x64, 0x8b, 0xc7]).unwrap();
+        assert_eq!(temperature.as_millidegrees_celsius(), 23_730);
+        let humidity = parse_humidity(&[0xa1, 0x33, 0x1c]).unwrap();
+        assert_eq!(humidity.as_millipercent(), 62_968);
+        assert_eq!(
+            parse_raw_partial_measurement(&[0xbe, 0xef, 0x92]),
+            Ok(0xbeef)
+        );
+    }
+
+    #[test]
+    fn parse_device_identifier() {
+        let msb = 0b0000_1000;
+        let lsb = 0b0000_0111;
+        let register = parse_id_register(&[msb, lsb, crc8(&[msb, lsb])]).unwrap();
+        assert_eq!(register, 0x0807);
+        assert_eq!(device_identifier(register), 0b0100_0111);
+    }
+}
@@ -0,0 +1,517 @@
+//! Recording and replaying of I²C bus traffic.
+//!
+//! The [`Recorder`] wraps an I²C bus and logs every transaction in a simple
+//! line based text format. The [`Replayer`] implements the I²C traits based on
+//! such a log, which allows reproducing bug reports from the field against the
+//! driver without access to the hardware.
+//!
+//! This module requires the `std` feature.
+//!
+//! ## File Format
+//!
+//! Every line contains one transaction: The time since the start of the
+//! recording in microseconds, the 7-bit address in hex, followed by the
+//! operations (`w:` for writes, `r:` for reads, with the data in hex) and
+//! optionally the error returned by the bus (prefixed by `!`). Empty lines
+//! and lines starting with `#` are ignored.
+//!
+//! ```text
+//! # shtcx bus recording
+//! 0 70 w:7866
+//! 12158 70 r:648bc7a1331c
+//! 12270 70 w:b098 !nack-address
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    string::String,
+    time::Instant,
+    vec::Vec,
+};
+
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// A single recorded I²C operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordedOperation {
+    /// Data written to the device.
+    Write(Vec<u8>),
+    /// Data read from the device.
+    Read(Vec<u8>),
+}
+
+/// A single recorded I²C transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedTransaction {
+    /// Time since the start of the recording in microseconds.
+    pub timestamp_us: u64,
+    /// The 7-bit device address.
+    pub address: u8,
+    /// The operations of this transaction.
+    pub operations: Vec<RecordedOperation>,
+    /// The error returned by the bus, if any.
+    pub error: Option<ErrorKind>,
+}
+
+impl RecordedTransaction {
+    /// Format the transaction as a line (without line terminator).
+    pub fn to_line(&self) -> String {
+        let mut line = String::new();
+        let _ = write!(line, "{} {:02x}", self.timestamp_us, self.address);
+        for op in &self.operations {
+            let (prefix, data) = match op {
+                RecordedOperation::Write(data) => ("w", data),
+                RecordedOperation::Read(data) => ("r", data),
+            };
+            let _ = write!(line, " {}:", prefix);
+            for byte in data {
+                let _ = write!(line, "{:02x}", byte);
+            }
+        }
+        if let Some(kind) = self.error {
+            let _ = write!(line, " !{}", error_name(kind));
+        }
+        line
+    }
+
+    /// Parse a transaction from a line.
+    ///
+    /// Return `None` if the line is not a valid transaction.
+    pub fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let timestamp_us = parts.next()?.parse().ok()?;
+        let address = u8::from_str_radix(parts.next()?, 16).ok()?;
+        let mut operations = Vec::new();
+        let mut error = None;
+        for part in parts {
+            if let Some(name) = part.strip_prefix('!') {
+                error = Some(error_from_name(name)?);
+            } else if let Some(hex) = part.strip_prefix("w:") {
+                operations.push(RecordedOperation::Write(parse_hex(hex)?));
+            } else if let Some(hex) = part.strip_prefix("r:") {
+                operations.push(RecordedOperation::Read(parse_hex(hex)?));
+            } else {
+                return None;
+            }
+        }
+        Some(Self {
+            timestamp_us,
+            address,
+            operations,
+            error,
+        })
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn error_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Bus => "bus",
+        ErrorKind::ArbitrationLoss => "arbitration-loss",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => "nack-address",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data) => "nack-data",
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown) => "nack",
+        ErrorKind::Overrun => "overrun",
+        _ => "other",
+    }
+}
+
+fn error_from_name(name: &str) -> Option<ErrorKind> {
+    Some(match name {
+        "bus" => ErrorKind::Bus,
+        "arbitration-loss" => ErrorKind::ArbitrationLoss,
+        "nack-address" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+        "nack-data" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data),
+        "nack" => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Unknown),
+        "overrun" => ErrorKind::Overrun,
+        "other" => ErrorKind::Other,
+        _ => return None,
+    })
+}
+
+/// I²C bus wrapper that records all transactions to a writer.
+///
+/// Recording errors don't affect the bus communication. The first one is
+/// returned by [`finish`](Recorder::finish).
+#[derive(Debug)]
+pub struct Recorder<I2C, W: Write> {
+    i2c: I2C,
+    writer: W,
+    start: Instant,
+    io_error: Option<io::Error>,
+}
+
+impl<I2C, W: Write> Recorder<I2C, W> {
+    /// Wrap an I²C bus and record its traffic to `writer`.
+    pub fn new(i2c: I2C, mut writer: W) -> Self {
+        let io_error = writeln!(writer, "# shtcx bus recording").err();
+        Self {
+            i2c,
+            writer,
+            start: Instant::now(),
+            io_error,
+        }
+    }
+
+    /// Stop recording, return the I²C bus and the writer.
+    ///
+    /// If writing the recording failed, the first error is returned.
+    pub fn finish(mut self) -> Result<(I2C, W), io::Error> {
+        if let Some(e) = self.io_error {
+            return Err(e);
+        }
+        self.writer.flush()?;
+        Ok((self.i2c, self.writer))
+    }
+
+    fn record<E: i2c::Error>(
+        &mut self,
+        address: u8,
+        operations: Vec<RecordedOperation>,
+        result: &Result<(), E>,
+    ) {
+        let transaction = RecordedTransaction {
+            timestamp_us: self.start.elapsed().as_micros() as u64,
+            address,
+            operations,
+            error: result.as_ref().err().map(|e| e.kind()),
+        };
+        if self.io_error.is_none() {
+            self.io_error = writeln!(self.writer, "{}", transaction.to_line()).err();
+        }
+    }
+}
+
+impl<I2C: ErrorType, W: Write> ErrorType for Recorder<I2C, W> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c, W: Write> I2c for Recorder<I2C, W> {
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let result = self.i2c.read(address, read);
+        self.record(
+            address,
+            vec![RecordedOperation::Read(read.to_vec())],
+            &result,
+        );
+        result
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let result = self.i2c.write(address, write);
+        self.record(
+            address,
+            vec![RecordedOperation::Write(write.to_vec())],
+            &result,
+        );
+        result
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.i2c.write_read(address, write, read);
+        let operations = vec![
+            RecordedOperation::Write(write.to_vec()),
+            RecordedOperation::Read(read.to_vec()),
+        ];
+        self.record(address, operations, &result);
+        result
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let result = self.i2c.transaction(address, operations);
+        let recorded = operations
+            .iter()
+            .map(|op| match op {
+                Operation::Write(data) => RecordedOperation::Write(data.to_vec()),
+                Operation::Read(data) => RecordedOperation::Read(data.to_vec()),
+            })
+            .collect();
+        self.record(address, recorded, &result);
+        result
+    }
+}
+
+/// Error returned by the [`Replayer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The recorded transaction failed with this error.
+    Recorded(ErrorKind),
+    /// The transaction with the given index diverges from the recording.
+    Divergence(usize),
+    /// All recorded transactions have been replayed.
+    Exhausted,
+}
+
+impl i2c::Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ReplayError::Recorded(kind) => *kind,
+            ReplayError::Divergence(_) | ReplayError::Exhausted => ErrorKind::Other,
+        }
+    }
+}
+
+/// Error returned when parsing a recording fails.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the recording failed.
+    Io(io::Error),
+    /// The line with the given (1-based) number is invalid.
+    InvalidLine(usize),
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+/// I²C bus implementation that replays a recording.
+///
+/// Every transaction must match the next recorded transaction (address,
+/// operation types, written data and read lengths), otherwise
+/// [`ReplayError::Divergence`] is returned and all further transactions fail.
+/// Transactions after the end of the recording fail with
+/// [`ReplayError::Exhausted`].
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    transactions: Vec<RecordedTransaction>,
+    position: usize,
+    diverged: bool,
+    exhausted: bool,
+}
+
+impl Replayer {
+    /// Create a replayer from a list of transactions.
+    pub fn new(transactions: Vec<RecordedTransaction>) -> Self {
+        Self {
+            transactions,
+            position: 0,
+            diverged: false,
+            exhausted: false,
+        }
+    }
+
+    /// Parse a recording.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ParseError> {
+        let mut transactions = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            transactions
+                .push(RecordedTransaction::from_line(line).ok_or(ParseError::InvalidLine(i + 1))?);
+        }
+        Ok(Self::new(transactions))
+    }
+
+    /// Return the number of transactions that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.transactions.len() - self.position
+    }
+
+    /// Ensure that exactly the whole recording was replayed without
+    /// divergence.
+    pub fn done(self) -> Result<(), ReplayError> {
+        if self.exhausted {
+            Err(ReplayError::Exhausted)
+        } else if self.diverged || self.remaining() > 0 {
+            Err(ReplayError::Divergence(self.position))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl ErrorType for Replayer {
+    type Error = ReplayError;
+}
+
+impl I2c for Replayer {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if self.diverged {
+            return Err(ReplayError::Divergence(self.position));
+        }
+        let index = self.position;
+        let Some(recorded) = self.transactions.get(index) else {
+            self.exhausted = true;
+            return Err(ReplayError::Exhausted);
+        };
+        let matches = recorded.address == address
+            && recorded.operations.len() == operations.len()
+            && recorded
+                .operations
+                .iter()
+                .zip(operations.iter())
+                .all(|(rec, op)| match (rec, op) {
+                    (RecordedOperation::Write(expected), Operation::Write(data)) => {
+                        expected.as_slice() == *data
+                    }
+                    (RecordedOperation::Read(response), Operation::Read(buf)) => {
+                        response.len() == buf.len()
+                    }
+                    _ => false,
+                });
+        if !matches {
+            self.diverged = true;
+            return Err(ReplayError::Divergence(index));
+        }
+        for (rec, op) in recorded.operations.iter().zip(operations.iter_mut()) {
+            if let (RecordedOperation::Read(response), Operation::Read(buf)) = (rec, op) {
+                buf.copy_from_slice(response);
+            }
+        }
+        self.position += 1;
+        match recorded.error {
+            Some(kind) => Err(ReplayError::Recorded(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, Error, LowPower, PowerMode};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    /// Measurement result from the datasheet (section 5.4).
+    const RESULT: [u8; 6] = [0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c];
+
+    #[test]
+    fn line_roundtrip() {
+        let transaction = RecordedTransaction {
+            timestamp_us: 12158,
+            address: SHT_ADDR,
+            operations: vec![
+                RecordedOperation::Write(vec![0xef, 0xc8]),
+                RecordedOperation::Read(vec![0x08, 0x07, 0x5b]),
+            ],
+            error: Some(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+        };
+        let line = transaction.to_line();
+        assert_eq!(line, "12158 70 w:efc8 r:08075b !nack-data");
+        assert_eq!(RecordedTransaction::from_line(&line), Some(transaction));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(RecordedTransaction::from_line(""), None);
+        assert_eq!(RecordedTransaction::from_line("12 70 x:00"), None);
+        assert_eq!(RecordedTransaction::from_line("12 70 w:0"), None);
+        assert_eq!(RecordedTransaction::from_line("12 70 w:zz"), None);
+        assert_eq!(RecordedTransaction::from_line("12 70 !unknown"), None);
+
+        let err = Replayer::from_reader("# header\n\n0 70 w:7866\nfoo\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidLine(4)));
+    }
+
+    /// Record a session with the driver, then replay it.
+    #[test]
+    fn record_and_replay() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, RESULT.to_vec()),
+            Transaction::write(SHT_ADDR, vec![0xb0, 0x98]).with_error(ErrorKind::Other),
+        ];
+        let recorder = Recorder::new(I2cMock::new(&expectations), Vec::new());
+        let mut sht = shtc3(recorder);
+        let recorded = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(sht.sleep(), Err(Error::I2c(ErrorKind::Other)));
+        let (mut mock, log) = sht.destroy().finish().unwrap();
+        mock.done();
+
+        let log = String::from_utf8(log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "# shtcx bus recording");
+        assert!(lines[1].ends_with(" 70 w:7866"));
+        assert!(lines[2].ends_with(" 70 r:648bc7a1331c"));
+        assert!(lines[3].ends_with(" 70 w:b098 !other"));
+
+        let replayer = Replayer::from_reader(log.as_bytes()).unwrap();
+        assert_eq!(replayer.remaining(), 3);
+        let mut sht = shtc3(replayer);
+        let replayed = sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(replayed, recorded);
+        assert_eq!(
+            sht.sleep(),
+            Err(Error::I2c(ReplayError::Recorded(ErrorKind::Other)))
+        );
+        sht.destroy().done().unwrap();
+    }
+
+    #[test]
+    fn divergence() {
+        let log = "0 70 w:7866\n12158 70 r:648bc7a1331c\n";
+        let replayer = Replayer::from_reader(log.as_bytes()).unwrap();
+        let mut sht = shtc3(replayer);
+
+        // Low power instead of normal mode measurement
+        assert_eq!(
+            sht.start_measurement(PowerMode::LowPower, 0),
+            Err(Error::I2c(ReplayError::Divergence(0)))
+        );
+        // Once diverged, everything fails
+        assert_eq!(
+            sht.start_measurement(PowerMode::NormalMode, 0),
+            Err(Error::I2c(ReplayError::Divergence(0)))
+        );
+        assert_eq!(sht.destroy().done(), Err(ReplayError::Divergence(0)));
+    }
+
+    #[test]
+    fn incomplete_and_exhausted() {
+        let log = "0 70 w:7866\n12158 70 r:648bc7a1331c\n";
+
+        let replayer = Replayer::from_reader(log.as_bytes()).unwrap();
+        let mut sht = shtc3(replayer);
+        let _pending = sht.start_measurement(PowerMode::NormalMode, 0).unwrap();
+        assert_eq!(sht.destroy().done(), Err(ReplayError::Divergence(1)));
+
+        let replayer = Replayer::from_reader(log.as_bytes()).unwrap();
+        let mut sht = shtc3(replayer);
+        sht.measure(PowerMode::NormalMode, &mut NoopDelay).unwrap();
+        assert_eq!(
+            sht.measure(PowerMode::NormalMode, &mut NoopDelay),
+            Err(Error::I2c(ReplayError::Exhausted))
+        );
+        // Replaying more than the recording fails, even though no
+        // transactions remain
+        let replayer = sht.destroy();
+        assert_eq!(replayer.remaining(), 0);
+        assert_eq!(replayer.done(), Err(ReplayError::Exhausted));
+    }
+}
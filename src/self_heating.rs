@@ -0,0 +1,346 @@
+//! Self-heating compensation based on the measurement duty cycle.
+//!
+//! The sensor dissipates power while measuring. At high sampling rates (e.g.
+//! several measurements per second), it heats up and the measured
+//! temperature drifts upwards. The [`SelfHeating`] tracker estimates the duty
+//! cycle (the fraction of time spent measuring) with a first-order thermal
+//! model and optionally subtracts the resulting temperature rise from the
+//! measurements. If the duty cycle exceeds a configured limit, it either
+//! flags the measurements or throttles them.
+//!
+//! The temperature rise at full duty cycle and the thermal time constant
+//! depend on the PCB and enclosure and must be determined experimentally.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::self_heating::{DutyCyclePolicy, SelfHeating, SelfHeatingConfig};
+//! use shtcx::PowerMode;
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! # fn now_ms() -> u64 { 0 }
+//! let mut delay = Delay;
+//! let mut self_heating = SelfHeating::new(SelfHeatingConfig {
+//!     max_duty_cycle_permille: 100,
+//!     policy: DutyCyclePolicy::Throttle,
+//!     full_duty_cycle_heating: 4_000,
+//!     time_constant_ms: 30_000,
+//! });
+//! loop {
+//!     match self_heating.measure(&mut sht, PowerMode::NormalMode, now_ms(), &mut delay) {
+//!         Ok(reading) => println!("{:?}", reading.measurement),
+//!         Err(nb::Error::WouldBlock) => { /* Sampling too fast, try again later */ }
+//!         Err(nb::Error::Other(e)) => panic!("{:?}", e),
+//!     }
+//! }
+//! ```
+
+use embedded_hal::{
+    delay::DelayNs,
+    i2c::{I2c, SevenBitAddress},
+};
+
+use crate::{
+    calibration::Calibration, Error, Measurement, MeasurementDuration, PowerMode, ShtCx, ShtSensor,
+};
+
+/// What happens when a measurement would exceed the duty cycle limit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DutyCyclePolicy {
+    /// Measure anyway, but flag the reading (see
+    /// [`SelfHeatingReading::over_limit`]).
+    Warn,
+    /// Don't measure (return [`WouldBlock`](nb::Error::WouldBlock)).
+    Throttle,
+}
+
+/// Configuration of a [`SelfHeating`] tracker.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SelfHeatingConfig {
+    /// Maximum duty cycle in permille (1000 disables the limit).
+    pub max_duty_cycle_permille: u16,
+    /// What happens when the limit would be exceeded.
+    pub policy: DutyCyclePolicy,
+    /// Temperature rise in m°C when measuring continuously (0 disables the
+    /// correction).
+    pub full_duty_cycle_heating: u32,
+    /// Thermal time constant of the sensor in milliseconds (at least 1).
+    pub time_constant_ms: u32,
+}
+
+/// A measurement corrected for self-heating.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SelfHeatingReading {
+    /// The corrected measurement.
+    pub measurement: Measurement,
+    /// The estimated temperature rise in m°C that was subtracted.
+    pub heating: u32,
+    /// The estimated duty cycle in permille (including this measurement).
+    pub duty_cycle_permille: u16,
+    /// Whether the duty cycle exceeds the configured limit.
+    pub over_limit: bool,
+}
+
+/// Tracks the measurement duty cycle and the resulting self-heating.
+///
+/// The measurement time is accumulated in a leaky integrator that decays
+/// with the thermal time constant. When sampling with a fixed period that is
+/// short compared to the time constant, the estimate converges to the
+/// measurement duration divided by the period.
+#[derive(Debug, Clone)]
+pub struct SelfHeating {
+    config: SelfHeatingConfig,
+    /// Accumulated measurement time in µs, decayed until `updated_ms`.
+    accumulated_us: f32,
+    updated_ms: Option<u64>,
+}
+
+impl SelfHeating {
+    /// Create a new tracker.
+    pub fn new(config: SelfHeatingConfig) -> Self {
+        Self {
+            config,
+            accumulated_us: 0.0,
+            updated_ms: None,
+        }
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &SelfHeatingConfig {
+        &self.config
+    }
+
+    /// Forget all previous measurements (e.g. after the sensor was powered
+    /// off for a long time).
+    pub fn reset(&mut self) {
+        self.accumulated_us = 0.0;
+        self.updated_ms = None;
+    }
+
+    /// Return the accumulated measurement time, decayed until `now_ms`.
+    fn accumulated_at(&self, now_ms: u64) -> f32 {
+        let Some(updated_ms) = self.updated_ms else {
+            return 0.0;
+        };
+        let elapsed_ms = now_ms.saturating_sub(updated_ms) as f32;
+        self.accumulated_us * libm::expf(-elapsed_ms / self.time_constant_ms())
+    }
+
+    fn time_constant_ms(&self) -> f32 {
+        self.config.time_constant_ms.max(1) as f32
+    }
+
+    /// Convert an accumulated measurement time to a duty cycle in permille.
+    fn duty_cycle(&self, accumulated_us: f32) -> u16 {
+        libm::roundf(accumulated_us / self.time_constant_ms()).min(1000.0) as u16
+    }
+
+    /// Return the estimated duty cycle in permille at `now_ms`.
+    pub fn duty_cycle_permille(&self, now_ms: u64) -> u16 {
+        self.duty_cycle(self.accumulated_at(now_ms))
+    }
+
+    /// Return the estimated temperature rise in m°C at `now_ms`.
+    pub fn heating(&self, now_ms: u64) -> u32 {
+        let duty_cycle = u64::from(self.duty_cycle_permille(now_ms));
+        (u64::from(self.config.full_duty_cycle_heating) * duty_cycle / 1000) as u32
+    }
+
+    /// Return whether a measurement of `duration_us` at `now_ms` would
+    /// exceed the duty cycle limit.
+    pub fn would_exceed(&self, now_ms: u64, duration_us: u32) -> bool {
+        let accumulated_us = self.accumulated_at(now_ms) + duration_us as f32;
+        self.duty_cycle(accumulated_us) > self.config.max_duty_cycle_permille
+    }
+
+    /// Record a measurement of `duration_us` that was started at `now_ms`.
+    ///
+    /// Use this for measurements that are not run through
+    /// [`measure`](SelfHeating::measure), e.g. non-blocking measurements.
+    pub fn record(&mut self, now_ms: u64, duration_us: u32) {
+        self.accumulated_us = self.accumulated_at(now_ms) + duration_us as f32;
+        self.updated_ms = Some(now_ms);
+    }
+
+    /// Subtract the estimated temperature rise at `now_ms` from a
+    /// measurement.
+    ///
+    /// The humidity is re-derived at the corrected temperature (see
+    /// [`Calibration`]).
+    pub fn compensate(&self, measurement: Measurement, now_ms: u64) -> Measurement {
+        Calibration {
+            temperature_offset: -(self.heating(now_ms).min(i32::MAX as u32) as i32),
+            ..Calibration::NONE
+        }
+        .apply(measurement)
+    }
+
+    /// Run a blocking measurement at `now_ms` (milliseconds of a monotonic
+    /// clock), record it and correct it for self-heating.
+    ///
+    /// The duration of the measurement is assumed to be the maximum
+    /// measurement duration of the sensor. If the measurement would exceed
+    /// the duty cycle limit and the policy is [`DutyCyclePolicy::Throttle`],
+    /// no measurement is done and [`WouldBlock`](nb::Error::WouldBlock) is
+    /// returned.
+    pub fn measure<S, I2C>(
+        &mut self,
+        sht: &mut ShtCx<S, I2C>,
+        mode: PowerMode,
+        now_ms: u64,
+        delay: &mut impl DelayNs,
+    ) -> nb::Result<SelfHeatingReading, Error<I2C::Error>>
+    where
+        S: ShtSensor + MeasurementDuration,
+        I2C: I2c<SevenBitAddress>,
+    {
+        let duration_us = S::max_measurement_duration(mode);
+        let over_limit = self.would_exceed(now_ms, duration_us);
+        if over_limit && self.config.policy == DutyCyclePolicy::Throttle {
+            return Err(nb::Error::WouldBlock);
+        }
+        let measurement = sht.measure(mode, delay)?;
+        self.record(now_ms, duration_us);
+        Ok(SelfHeatingReading {
+            measurement: self.compensate(measurement, now_ms),
+            heating: self.heating(now_ms),
+            duty_cycle_permille: self.duty_cycle_permille(now_ms),
+            over_limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, Humidity, Temperature};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    const CONFIG: SelfHeatingConfig = SelfHeatingConfig {
+        max_duty_cycle_permille: 100,
+        policy: DutyCyclePolicy::Warn,
+        full_duty_cycle_heating: 5_000,
+        time_constant_ms: 10_000,
+    };
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    /// Two normal mode SHTC3 measurements every 50 ms, like the monitor
+    /// example.
+    #[test]
+    fn steady_state() {
+        let mut self_heating = SelfHeating::new(CONFIG);
+        assert_eq!(self_heating.duty_cycle_permille(0), 0);
+        assert_eq!(self_heating.heating(0), 0);
+        let mut now_ms = 0;
+        for _ in 0..2_000 {
+            self_heating.record(now_ms, 12_100);
+            self_heating.record(now_ms + 12, 12_100);
+            now_ms += 50;
+        }
+        let duty_cycle = self_heating.duty_cycle_permille(now_ms);
+        assert!((480..=490).contains(&duty_cycle), "{}", duty_cycle);
+        let heating = self_heating.heating(now_ms);
+        assert!((2_400..=2_450).contains(&heating), "{}", heating);
+        assert!(self_heating.would_exceed(now_ms, 12_100));
+
+        // Cools down while idle
+        assert!(self_heating.heating(now_ms + 50_000) < 25);
+        assert!(!self_heating.would_exceed(now_ms + 50_000, 12_100));
+
+        self_heating.reset();
+        assert_eq!(self_heating.duty_cycle_permille(now_ms), 0);
+    }
+
+    #[test]
+    fn compensate() {
+        let mut self_heating = SelfHeating::new(CONFIG);
+        assert_eq!(
+            self_heating.compensate(m(25_000, 50_000), 0),
+            m(25_000, 50_000)
+        );
+        // 20 % duty cycle: 1 °C
+        self_heating.record(0, 2_000_000);
+        let compensated = self_heating.compensate(m(25_000, 50_000), 0);
+        assert_eq!(compensated.temperature.as_millidegrees_celsius(), 24_000);
+        assert!(compensated.humidity.as_millipercent() > 50_000);
+
+        // Correction disabled
+        let mut self_heating = SelfHeating::new(SelfHeatingConfig {
+            full_duty_cycle_heating: 0,
+            ..CONFIG
+        });
+        self_heating.record(0, 2_000_000);
+        assert_eq!(self_heating.duty_cycle_permille(0), 200);
+        assert_eq!(
+            self_heating.compensate(m(25_000, 50_000), 0),
+            m(25_000, 50_000)
+        );
+    }
+
+    fn measurement() -> [Transaction; 2] {
+        [
+            Transaction::write(SHT_ADDR, vec![0x78, 0x66]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+        ]
+    }
+
+    #[test]
+    fn warn() {
+        let expectations = [measurement(), measurement()].concat();
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let mut self_heating = SelfHeating::new(CONFIG);
+        // A single measurement of 12.1 ms with a time constant of 10 s:
+        // 1.21 ‰ (about 0.12 %), rounded to 1 ‰
+        let reading = self_heating
+            .measure(&mut sht, PowerMode::NormalMode, 0, &mut NoopDelay)
+            .unwrap();
+        assert_eq!(reading.duty_cycle_permille, 1);
+        assert_eq!(reading.heating, 5);
+        assert!(!reading.over_limit);
+        assert_eq!(
+            reading.measurement.temperature.as_millidegrees_celsius(),
+            23_725
+        );
+
+        // Too fast
+        self_heating.record(10, 1_000_000);
+        let reading = self_heating
+            .measure(&mut sht, PowerMode::NormalMode, 20, &mut NoopDelay)
+            .unwrap();
+        assert!(reading.over_limit);
+        assert_eq!(reading.duty_cycle_permille, 102);
+        sht.destroy().done();
+    }
+
+    #[test]
+    fn throttle() {
+        let expectations = measurement();
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let mut self_heating = SelfHeating::new(SelfHeatingConfig {
+            policy: DutyCyclePolicy::Throttle,
+            ..CONFIG
+        });
+        self_heating.record(0, 1_000_000);
+        assert_eq!(
+            self_heating.measure(&mut sht, PowerMode::NormalMode, 0, &mut NoopDelay),
+            Err(nb::Error::WouldBlock)
+        );
+        // Cooled down
+        assert!(self_heating
+            .measure(&mut sht, PowerMode::NormalMode, 1_000, &mut NoopDelay)
+            .is_ok());
+        sht.destroy().done();
+    }
+}
@@ -0,0 +1,259 @@
+//! Health statistics of the sensor communication.
+//!
+//! With the `statistics` feature enabled, every [`ShtCx`](crate::ShtCx)
+//! instance counts the commands sent, successful measurements and errors.
+//! Without the feature, the counters are zero-sized and all counting is
+//! compiled out.
+
+use embedded_hal::i2c::ErrorKind;
+
+use crate::protocol::Command;
+
+/// Number of I²C errors, by [`ErrorKind`].
+#[cfg(any(test, feature = "statistics"))]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct I2cErrorCounts {
+    /// Bus errors.
+    pub bus: u32,
+    /// Arbitration losses.
+    pub arbitration_loss: u32,
+    /// Missing acknowledges (of the address or data).
+    pub no_acknowledge: u32,
+    /// Overruns.
+    pub overrun: u32,
+    /// Other errors.
+    pub other: u32,
+}
+
+#[cfg(any(test, feature = "statistics"))]
+impl I2cErrorCounts {
+    /// Return the total number of I²C errors.
+    pub fn total(&self) -> u32 {
+        self.bus
+            .saturating_add(self.arbitration_loss)
+            .saturating_add(self.no_acknowledge)
+            .saturating_add(self.overrun)
+            .saturating_add(self.other)
+    }
+}
+
+/// Snapshot of the statistics counters of a driver instance.
+///
+/// See [`ShtCx::statistics`](crate::ShtCx::statistics). All counters
+/// saturate at `u32::MAX`.
+#[cfg(any(test, feature = "statistics"))]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Number of commands sent to the sensor (including failed attempts).
+    pub commands_sent: u32,
+    /// Number of measurement results read successfully.
+    pub measurements: u32,
+    /// Number of responses with an invalid CRC.
+    pub crc_errors: u32,
+    /// Number of I²C errors.
+    pub i2c_errors: I2cErrorCounts,
+    /// Number of soft resets.
+    pub resets: u32,
+    /// Number of wakeups from sleep mode.
+    pub wakeups: u32,
+    /// Number of polls of a measurement result that wasn't ready yet.
+    pub retries: u32,
+}
+
+/// The counters stored in the driver.
+#[cfg(any(test, feature = "statistics"))]
+pub(crate) type Counters = Statistics;
+
+/// The counters stored in the driver (disabled).
+#[cfg(not(any(test, feature = "statistics")))]
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Counters;
+
+#[cfg(any(test, feature = "statistics"))]
+impl Counters {
+    pub(crate) fn command(&mut self, command: &Command) {
+        self.commands_sent = self.commands_sent.saturating_add(1);
+        match command {
+            Command::SoftwareReset => self.resets = self.resets.saturating_add(1),
+            Command::WakeUp => self.wakeups = self.wakeups.saturating_add(1),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn measurement(&mut self) {
+        self.measurements = self.measurements.saturating_add(1);
+    }
+
+    pub(crate) fn crc_error(&mut self) {
+        self.crc_errors = self.crc_errors.saturating_add(1);
+    }
+
+    pub(crate) fn i2c_error(&mut self, kind: ErrorKind) {
+        let errors = &mut self.i2c_errors;
+        let counter = match kind {
+            ErrorKind::Bus => &mut errors.bus,
+            ErrorKind::ArbitrationLoss => &mut errors.arbitration_loss,
+            ErrorKind::NoAcknowledge(_) => &mut errors.no_acknowledge,
+            ErrorKind::Overrun => &mut errors.overrun,
+            _ => &mut errors.other,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    /// Count a poll that was not acknowledged (instead of an I²C error).
+    pub(crate) fn retry(&mut self) {
+        self.retries = self.retries.saturating_add(1);
+    }
+}
+
+#[cfg(not(any(test, feature = "statistics")))]
+impl Counters {
+    #[inline(always)]
+    pub(crate) fn command(&mut self, _command: &Command) {}
+
+    #[inline(always)]
+    pub(crate) fn measurement(&mut self) {}
+
+    #[inline(always)]
+    pub(crate) fn crc_error(&mut self) {}
+
+    #[inline(always)]
+    pub(crate) fn i2c_error(&mut self, _kind: ErrorKind) {}
+
+    #[inline(always)]
+    pub(crate) fn retry(&mut self) {}
+}
+
+/// Statistics counters.
+#[cfg(any(test, feature = "statistics"))]
+impl<S: crate::ShtSensor, I2C> crate::ShtCx<S, I2C> {
+    /// Return a snapshot of the statistics counters.
+    ///
+    /// Requires the `statistics` feature.
+    pub fn statistics(&self) -> Statistics {
+        self.counters
+    }
+
+    /// Reset all statistics counters to zero.
+    ///
+    /// Requires the `statistics` feature.
+    pub fn reset_statistics(&mut self) {
+        self.counters = Statistics::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_hal::i2c::NoAcknowledgeSource;
+    use embedded_hal_mock::eh1::{
+        delay::NoopDelay,
+        i2c::{Mock as I2cMock, Transaction},
+    };
+
+    use crate::{shtc3, Error, LowPower, PowerMode};
+
+    const SHT_ADDR: u8 = 0x70;
+
+    #[test]
+    fn measurements() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x35, 0x17]),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7]),
+            Transaction::write(SHT_ADDR, vec![0x80, 0x5d]),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        assert_eq!(sht.statistics(), Statistics::default());
+        sht.wakeup(&mut NoopDelay).unwrap();
+        sht.measure(PowerMode::LowPower, &mut NoopDelay).unwrap();
+        sht.measure_temperature(PowerMode::LowPower, &mut NoopDelay)
+            .unwrap();
+        sht.reset(&mut NoopDelay).unwrap();
+        assert_eq!(
+            sht.statistics(),
+            Statistics {
+                commands_sent: 4,
+                measurements: 2,
+                resets: 1,
+                wakeups: 1,
+                ..Statistics::default()
+            }
+        );
+        sht.reset_statistics();
+        assert_eq!(sht.statistics(), Statistics::default());
+        sht.destroy().done();
+    }
+
+    #[test]
+    fn errors() {
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]).with_error(ErrorKind::Bus),
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x00]),
+            Transaction::write(SHT_ADDR, vec![0xef, 0xc8]),
+            Transaction::read(SHT_ADDR, vec![0; 3]).with_error(ErrorKind::Other),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        assert_eq!(
+            sht.measure(PowerMode::LowPower, &mut NoopDelay),
+            Err(Error::I2c(ErrorKind::Bus))
+        );
+        assert_eq!(
+            sht.measure(PowerMode::LowPower, &mut NoopDelay),
+            Err(Error::Crc)
+        );
+        assert!(sht.raw_id_register().is_err());
+        let statistics = sht.statistics();
+        assert_eq!(statistics.commands_sent, 3);
+        assert_eq!(statistics.measurements, 0);
+        assert_eq!(statistics.crc_errors, 1);
+        assert_eq!(
+            statistics.i2c_errors,
+            I2cErrorCounts {
+                bus: 1,
+                other: 1,
+                ..I2cErrorCounts::default()
+            }
+        );
+        assert_eq!(statistics.i2c_errors.total(), 2);
+        sht.destroy().done();
+    }
+
+    /// Polls of a result that isn't ready yet are counted as retries.
+    #[test]
+    fn retries() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        let expectations = [
+            Transaction::write(SHT_ADDR, vec![0x60, 0x9c]),
+            Transaction::read(SHT_ADDR, vec![0; 6]).with_error(nack),
+            Transaction::read(SHT_ADDR, vec![0; 6]).with_error(nack),
+            Transaction::read(SHT_ADDR, vec![0x64, 0x8b, 0xc7, 0xa1, 0x33, 0x1c]),
+            Transaction::read(SHT_ADDR, vec![0; 6]).with_error(nack),
+            Transaction::read(SHT_ADDR, vec![0; 3]).with_error(ErrorKind::Bus),
+        ];
+        let mut sht = shtc3(I2cMock::new(&expectations));
+        let pending = sht.start_measurement(PowerMode::LowPower, 0).unwrap();
+        let (pending, err) = pending.poll(&mut sht).unwrap_err();
+        assert_eq!(err, nb::Error::WouldBlock);
+        assert_eq!(sht.poll_measurement_result(), Err(nb::Error::WouldBlock));
+        assert!(pending.poll(&mut sht).is_ok());
+        // Not polled, so this is an error
+        assert!(sht.get_measurement_result().is_err());
+        // Other errors of a poll aren't retries
+        assert!(matches!(
+            sht.poll_temperature_measurement_result(),
+            Err(nb::Error::Other(_))
+        ));
+        let statistics = sht.statistics();
+        assert_eq!(statistics.retries, 2);
+        assert_eq!(statistics.measurements, 1);
+        assert_eq!(statistics.i2c_errors.no_acknowledge, 1);
+        assert_eq!(statistics.i2c_errors.bus, 1);
+        assert_eq!(statistics.i2c_errors.total(), 2);
+        sht.destroy().done();
+    }
+}
@@ -0,0 +1,482 @@
+//! Persistent storage of calibration data.
+//!
+//! A [`CalibrationRecord`] contains the static [`Calibration`] and the
+//! fitted [`CalibrationProfile`] of a sensor. It is serialized into a
+//! versioned record protected by a CRC-32 checksum, which can be stored
+//! through the [`embedded_storage`] traits: Either on a NOR flash
+//! ([`NorFlash`] / [`ReadNorFlash`]) or on any other [`Storage`] /
+//! [`ReadStorage`].
+//!
+//! Records written by older versions of this crate are migrated when they
+//! are loaded. New records are always written in the current version.
+//!
+//! Requires the `embedded-storage` feature.
+//!
+//! ```
+//! # use embedded_storage::{ReadStorage, Storage};
+//! # struct Eeprom([u8; 256]);
+//! # impl ReadStorage for Eeprom {
+//! #     type Error = ();
+//! #     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+//! #         let offset = offset as usize;
+//! #         bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+//! #         Ok(())
+//! #     }
+//! #     fn capacity(&self) -> usize { self.0.len() }
+//! # }
+//! # impl Storage for Eeprom {
+//! #     fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+//! #         let offset = offset as usize;
+//! #         self.0[offset..offset + bytes.len()].copy_from_slice(bytes);
+//! #         Ok(())
+//! #     }
+//! # }
+//! # let mut eeprom = Eeprom([0xff; 256]);
+//! use shtcx::calibration::Calibration;
+//! use shtcx::storage::CalibrationRecord;
+//!
+//! let record = CalibrationRecord {
+//!     calibration: Calibration {
+//!         temperature_offset: -1_500,
+//!         ..Calibration::NONE
+//!     },
+//!     ..CalibrationRecord::default()
+//! };
+//! record.write_to(&mut eeprom, 0).unwrap();
+//!
+//! // After a reboot
+//! let loaded = CalibrationRecord::read_from(&mut eeprom, 0).unwrap();
+//! assert_eq!(loaded, record);
+//! ```
+
+use embedded_storage::{
+    nor_flash::{NorFlash, ReadNorFlash},
+    ReadStorage, Storage,
+};
+
+use crate::{
+    calibration::{Calibration, CalibrationProfile, InvalidProfile},
+    crc::crc32,
+};
+
+/// Magic bytes at the start of every record.
+const MAGIC: [u8; 2] = *b"SH";
+/// Length of the header: Magic, version and a reserved byte.
+const HEADER_LEN: usize = 4;
+/// Length of a serialized [`Calibration`].
+const CALIBRATION_LEN: usize = 12;
+/// Length of the CRC-32 checksum.
+const CRC_LEN: usize = 4;
+
+/// Length of a version 1 record (static calibration only).
+const V1_LEN: usize = HEADER_LEN + CALIBRATION_LEN + CRC_LEN;
+/// Length of a version 2 record (static calibration and profile).
+const V2_LEN: usize = HEADER_LEN + CALIBRATION_LEN + CalibrationProfile::SERIALIZED_LEN + CRC_LEN;
+
+/// All possible errors when loading or storing a [`CalibrationRecord`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecordError<E> {
+    /// Error of the underlying storage
+    Storage(E),
+    /// There is no record at the given offset (e.g. the flash is erased)
+    NotFound,
+    /// The record is corrupted
+    Crc,
+    /// The record was written by a newer version of this crate
+    UnsupportedVersion(u8),
+    /// The record contents are invalid
+    Invalid,
+}
+
+impl<E> From<InvalidProfile> for RecordError<E> {
+    fn from(_: InvalidProfile) -> Self {
+        RecordError::Invalid
+    }
+}
+
+/// Calibration data of a sensor that can be stored persistently.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CalibrationRecord {
+    /// The static calibration.
+    pub calibration: Calibration,
+    /// The fitted calibration profile.
+    pub profile: CalibrationProfile,
+}
+
+impl CalibrationRecord {
+    /// The version of records written by [`to_bytes`](CalibrationRecord::to_bytes).
+    ///
+    /// - Version 1: Static calibration only
+    /// - Version 2: Static calibration and calibration profile
+    pub const VERSION: u8 = 2;
+
+    /// Number of bytes occupied by a record in storage.
+    ///
+    /// The record is padded with `0xff` to a multiple of 32 bytes, so it can
+    /// be written to NOR flashes with a write size of up to 32 bytes.
+    pub const LEN: usize = V2_LEN.next_multiple_of(32);
+
+    /// Serialize the record in the current version.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0xff; Self::LEN];
+        bytes[..2].copy_from_slice(&MAGIC);
+        bytes[2] = Self::VERSION;
+        bytes[3] = 0;
+        write_calibration(&self.calibration, &mut bytes[HEADER_LEN..]);
+        let profile = HEADER_LEN + CALIBRATION_LEN;
+        bytes[profile..profile + CalibrationProfile::SERIALIZED_LEN]
+            .copy_from_slice(&self.profile.to_bytes());
+        let crc = crc32(&bytes[..V2_LEN - CRC_LEN]);
+        bytes[V2_LEN - CRC_LEN..V2_LEN].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a record of the current or an older version.
+    ///
+    /// Records of older versions are migrated: Data that didn't exist in the
+    /// older version is set to its default (e.g. the profile of a version 1
+    /// record is [`CalibrationProfile::NONE`]).
+    pub fn from_bytes<E>(bytes: &[u8]) -> Result<Self, RecordError<E>> {
+        let len = record_len(bytes)?;
+        let version = bytes[2];
+        let bytes = bytes.get(..len).ok_or(RecordError::Invalid)?;
+        let (data, crc) = bytes.split_at(len - CRC_LEN);
+        if crc32(data).to_le_bytes() != crc {
+            return Err(RecordError::Crc);
+        }
+
+        let calibration = read_calibration(&data[HEADER_LEN..]);
+        let profile = if version >= 2 {
+            let profile = &data[HEADER_LEN + CALIBRATION_LEN..];
+            CalibrationProfile::from_bytes(profile.try_into().map_err(|_| RecordError::Invalid)?)?
+        } else {
+            CalibrationProfile::NONE
+        };
+        Ok(Self {
+            calibration,
+            profile,
+        })
+    }
+
+    /// Load a record from `storage` at `offset`.
+    ///
+    /// Only the bytes of the stored record version are read, so older
+    /// (shorter) records may end right at the end of the storage.
+    pub fn read_from<S: ReadStorage>(
+        storage: &mut S,
+        offset: u32,
+    ) -> Result<Self, RecordError<S::Error>> {
+        Self::read_with(|offset, bytes| storage.read(offset, bytes), offset, 1)
+    }
+
+    /// Store the record in `storage` at `offset`.
+    pub fn write_to<S: Storage>(
+        &self,
+        storage: &mut S,
+        offset: u32,
+    ) -> Result<(), RecordError<S::Error>> {
+        storage
+            .write(offset, &self.to_bytes())
+            .map_err(RecordError::Storage)
+    }
+
+    /// Load a record from a NOR flash at `offset`.
+    ///
+    /// `offset` must be aligned to the read size of the flash. Only the
+    /// bytes of the stored record version are read (rounded up to the read
+    /// size).
+    pub fn read_from_flash<F: ReadNorFlash>(
+        flash: &mut F,
+        offset: u32,
+    ) -> Result<Self, RecordError<F::Error>> {
+        Self::read_with(
+            |offset, bytes| flash.read(offset, bytes),
+            offset,
+            F::READ_SIZE,
+        )
+    }
+
+    /// Read the header first, then the rest of the record of the stored
+    /// version. All reads are multiples of `read_size` long.
+    fn read_with<E>(
+        mut read: impl FnMut(u32, &mut [u8]) -> Result<(), E>,
+        offset: u32,
+        read_size: usize,
+    ) -> Result<Self, RecordError<E>> {
+        let mut bytes = [0; Self::LEN];
+        let header_len = HEADER_LEN.next_multiple_of(read_size).min(Self::LEN);
+        read(offset, &mut bytes[..header_len]).map_err(RecordError::Storage)?;
+        let len = record_len(&bytes[..header_len])?
+            .next_multiple_of(read_size)
+            .min(Self::LEN);
+        if len > header_len {
+            read(offset + header_len as u32, &mut bytes[header_len..len])
+                .map_err(RecordError::Storage)?;
+        }
+        Self::from_bytes(&bytes[..len])
+    }
+
+    /// Store the record in a NOR flash at `offset`.
+    ///
+    /// The sectors containing the record are erased first, so `offset` must
+    /// be aligned to the erase size of the flash and the rest of the sectors
+    /// must not contain other data.
+    pub fn write_to_flash<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        offset: u32,
+    ) -> Result<(), RecordError<F::Error>> {
+        let erase_len = Self::LEN.next_multiple_of(F::ERASE_SIZE) as u32;
+        flash
+            .erase(offset, offset + erase_len)
+            .map_err(RecordError::Storage)?;
+        flash
+            .write(offset, &self.to_bytes())
+            .map_err(RecordError::Storage)
+    }
+}
+
+/// Check the header of a record and return the length of the record.
+fn record_len<E>(header: &[u8]) -> Result<usize, RecordError<E>> {
+    if header.len() < HEADER_LEN || header[..2] != MAGIC {
+        return Err(RecordError::NotFound);
+    }
+    match header[2] {
+        1 => Ok(V1_LEN),
+        2 => Ok(V2_LEN),
+        version => Err(RecordError::UnsupportedVersion(version)),
+    }
+}
+
+fn write_calibration(calibration: &Calibration, buf: &mut [u8]) {
+    buf[..4].copy_from_slice(&calibration.temperature_offset.to_le_bytes());
+    buf[4..8].copy_from_slice(&calibration.humidity_offset.to_le_bytes());
+    buf[8..12].copy_from_slice(&calibration.humidity_gain_ppm.to_le_bytes());
+}
+
+fn read_calibration(buf: &[u8]) -> Calibration {
+    let bytes_at = |i: usize| [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+    Calibration {
+        temperature_offset: i32::from_le_bytes(bytes_at(0)),
+        humidity_offset: i32::from_le_bytes(bytes_at(4)),
+        humidity_gain_ppm: i32::from_le_bytes(bytes_at(8)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use embedded_storage::nor_flash::{
+        check_erase, check_read, check_write, ErrorType, NorFlashErrorKind,
+    };
+
+    use crate::calibration::{Breakpoint, Breakpoints, Correction};
+
+    /// In-memory NOR flash: Erasing sets all bits, writing can only clear
+    /// bits.
+    struct Flash([u8; 1024]);
+
+    impl ErrorType for Flash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for Flash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl NorFlash for Flash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 128;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.0[from as usize..to as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            for (cell, byte) in self.0[offset as usize..].iter_mut().zip(bytes) {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+
+    /// In-memory EEPROM.
+    struct Eeprom(Vec<u8>);
+
+    impl ReadStorage for Eeprom {
+        type Error = ();
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(self.0.get(offset..offset + bytes.len()).ok_or(())?);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl Storage for Eeprom {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            self.0
+                .get_mut(offset..offset + bytes.len())
+                .ok_or(())?
+                .copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    fn record() -> CalibrationRecord {
+        let breakpoints = Breakpoints::new(&[
+            Breakpoint {
+                measured: 0,
+                reference: 500,
+            },
+            Breakpoint {
+                measured: 40_000,
+                reference: 39_000,
+            },
+        ])
+        .unwrap();
+        CalibrationRecord {
+            calibration: Calibration {
+                temperature_offset: -1_500,
+                humidity_offset: 2_000,
+                humidity_gain_ppm: 990_000,
+            },
+            profile: CalibrationProfile {
+                temperature: Correction::Piecewise(breakpoints),
+                humidity: Correction::NONE,
+            },
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        let bytes = record().to_bytes();
+        assert_eq!(bytes.len(), 160);
+        assert_eq!(bytes[..4], [b'S', b'H', 2, 0]);
+        assert!(bytes[V2_LEN..].iter().all(|byte| *byte == 0xff));
+        assert_eq!(CalibrationRecord::from_bytes::<()>(&bytes), Ok(record()));
+        // Padding is ignored
+        assert_eq!(
+            CalibrationRecord::from_bytes::<()>(&bytes[..V2_LEN]),
+            Ok(record())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let from_bytes = CalibrationRecord::from_bytes::<()>;
+        assert_eq!(from_bytes(&[0xff; 160]), Err(RecordError::NotFound));
+        assert_eq!(from_bytes(&[]), Err(RecordError::NotFound));
+
+        let mut bytes = record().to_bytes();
+        bytes[10] ^= 0x01;
+        assert_eq!(from_bytes(&bytes), Err(RecordError::Crc));
+        assert_eq!(from_bytes(&bytes[..100]), Err(RecordError::Invalid));
+
+        let mut bytes = record().to_bytes();
+        bytes[2] = 3;
+        assert_eq!(from_bytes(&bytes), Err(RecordError::UnsupportedVersion(3)));
+
+        // Valid CRC, but invalid profile
+        let mut bytes = record().to_bytes();
+        bytes[HEADER_LEN + CALIBRATION_LEN] = 7;
+        let crc = crc32(&bytes[..V2_LEN - CRC_LEN]);
+        bytes[V2_LEN - CRC_LEN..V2_LEN].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(from_bytes(&bytes), Err(RecordError::Invalid));
+    }
+
+    /// Version 1 records only contain the static calibration.
+    #[test]
+    fn migrate_v1() {
+        let mut bytes = vec![b'S', b'H', 1, 0];
+        bytes.extend_from_slice(&(-1_500i32).to_le_bytes());
+        bytes.extend_from_slice(&2_000i32.to_le_bytes());
+        bytes.extend_from_slice(&990_000u32.to_le_bytes());
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let mut eeprom = Eeprom(vec![0xff; 256]);
+        eeprom.write(32, &bytes).unwrap();
+        let loaded = CalibrationRecord::read_from(&mut eeprom, 32).unwrap();
+        assert_eq!(loaded.calibration, record().calibration);
+        assert_eq!(loaded.profile, CalibrationProfile::NONE);
+
+        // At the end of a small EEPROM
+        let mut eeprom = Eeprom(vec![0xff; 64]);
+        eeprom.write(44, &bytes).unwrap();
+        let loaded = CalibrationRecord::read_from(&mut eeprom, 44).unwrap();
+        assert_eq!(loaded.calibration, record().calibration);
+        assert_eq!(loaded.profile, CalibrationProfile::NONE);
+
+        // At the end of a flash
+        let mut flash = Flash([0xff; 1024]);
+        flash.write(1004, &bytes).unwrap();
+        let loaded = CalibrationRecord::read_from_flash(&mut flash, 1004).unwrap();
+        assert_eq!(loaded.calibration, record().calibration);
+    }
+
+    #[test]
+    fn storage() {
+        let mut eeprom = Eeprom(vec![0; 200]);
+        assert_eq!(
+            CalibrationRecord::read_from(&mut eeprom, 0),
+            Err(RecordError::NotFound)
+        );
+        record().write_to(&mut eeprom, 40).unwrap();
+        assert_eq!(CalibrationRecord::read_from(&mut eeprom, 40), Ok(record()));
+        assert_eq!(
+            record().write_to(&mut eeprom, 41),
+            Err(RecordError::Storage(()))
+        );
+    }
+
+    #[test]
+    fn flash() {
+        let mut flash = Flash([0; 1024]);
+        record().write_to_flash(&mut flash, 256).unwrap();
+        assert_eq!(
+            CalibrationRecord::read_from_flash(&mut flash, 256),
+            Ok(record())
+        );
+        // Overwriting requires an erase
+        CalibrationRecord::default()
+            .write_to_flash(&mut flash, 256)
+            .unwrap();
+        assert_eq!(
+            CalibrationRecord::read_from_flash(&mut flash, 256),
+            Ok(CalibrationRecord::default())
+        );
+        // The second sector of the record is erased as well
+        assert!(flash.0[256 + 160..512].iter().all(|byte| *byte == 0xff));
+        assert_eq!(flash.0[512], 0);
+
+        assert_eq!(
+            record().write_to_flash(&mut flash, 100),
+            Err(RecordError::Storage(NorFlashErrorKind::NotAligned))
+        );
+        assert_eq!(
+            record().write_to_flash(&mut flash, 1024),
+            Err(RecordError::Storage(NorFlashErrorKind::OutOfBounds))
+        );
+    }
+}
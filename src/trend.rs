@@ -0,0 +1,214 @@
+//! Trend detection.
+//!
+//! The [`TrendTracker`] keeps the last `N` timestamped measurements,
+//! calculates the rate of change of temperature and humidity with a linear
+//! regression (see [`History::rate_of_change`]) and classifies it as rising,
+//! falling or steady. This can be used to detect e.g. open windows or HVAC
+//! failures.
+//!
+//! ```no_run
+//! # use linux_embedded_hal::{Delay, I2cdev};
+//! use shtcx::{trend::{Direction, TrendConfig, TrendTracker}, PowerMode};
+//!
+//! # let mut sht = shtcx::shtc3(I2cdev::new("/dev/i2c-1").unwrap());
+//! # fn now_ms() -> u64 { 0 }
+//! let mut delay = Delay;
+//! // Rising or falling if the temperature changes by more than 0.5 °C/min
+//! // or the humidity by more than 2 %RH/min
+//! let mut tracker = TrendTracker::<10>::new(TrendConfig {
+//!     temperature_per_minute: 500,
+//!     humidity_per_minute: 2_000,
+//! });
+//! loop {
+//!     let measurement = sht.measure(PowerMode::LowPower, &mut delay).unwrap();
+//!     if let Some(trend) = tracker.update(measurement, now_ms()) {
+//!         if trend.temperature == Direction::Falling {
+//!             println!("Window open?");
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::{
+    history::{History, RateOfChange},
+    Measurement,
+};
+
+/// Direction of a trend.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// The value increases faster than the configured threshold.
+    Rising,
+    /// The value decreases faster than the configured threshold.
+    Falling,
+    /// The value changes slower than the configured threshold.
+    Steady,
+}
+
+impl Direction {
+    fn classify(rate: i32, threshold: u32) -> Self {
+        if rate.unsigned_abs() <= threshold {
+            Direction::Steady
+        } else if rate > 0 {
+            Direction::Rising
+        } else {
+            Direction::Falling
+        }
+    }
+}
+
+/// Sensitivity of a [`TrendTracker`].
+///
+/// Rates of change up to these thresholds (inclusive) are considered steady.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TrendConfig {
+    /// Threshold in milli-degrees celsius per minute.
+    pub temperature_per_minute: u32,
+    /// Threshold in 1/1000 %RH per minute.
+    pub humidity_per_minute: u32,
+}
+
+/// A classified trend.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trend {
+    /// The rate of change.
+    pub rate: RateOfChange,
+    /// Direction of the temperature.
+    pub temperature: Direction,
+    /// Direction of the humidity.
+    pub humidity: Direction,
+}
+
+/// Tracks the trend over the last `N` timestamped measurements.
+#[derive(Debug, Clone)]
+pub struct TrendTracker<const N: usize> {
+    config: TrendConfig,
+    history: History<N>,
+}
+
+impl<const N: usize> TrendTracker<N> {
+    /// Create a new trend tracker.
+    pub fn new(config: TrendConfig) -> Self {
+        Self {
+            config,
+            history: History::new(),
+        }
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &TrendConfig {
+        &self.config
+    }
+
+    /// Change the sensitivity.
+    pub fn set_config(&mut self, config: TrendConfig) {
+        self.config = config;
+    }
+
+    /// Return the measurements the trend is based on.
+    pub fn history(&self) -> &History<N> {
+        &self.history
+    }
+
+    /// Forget all measurements.
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Add a measurement taken at `timestamp_ms` (milliseconds of a monotonic
+    /// clock) and return the current trend.
+    ///
+    /// See [`trend`](TrendTracker::trend).
+    pub fn update(&mut self, measurement: Measurement, timestamp_ms: u64) -> Option<Trend> {
+        self.history.push_at(measurement, timestamp_ms);
+        self.trend()
+    }
+
+    /// Return the current trend.
+    ///
+    /// Return `None` until at least two measurements with different
+    /// timestamps have been added.
+    pub fn trend(&self) -> Option<Trend> {
+        let rate = self.history.rate_of_change()?;
+        Some(Trend {
+            rate,
+            temperature: Direction::classify(
+                rate.temperature_per_minute,
+                self.config.temperature_per_minute,
+            ),
+            humidity: Direction::classify(
+                rate.humidity_per_minute,
+                self.config.humidity_per_minute,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Humidity, Temperature};
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    const CONFIG: TrendConfig = TrendConfig {
+        temperature_per_minute: 500,
+        humidity_per_minute: 2_000,
+    };
+
+    #[test]
+    fn classify() {
+        assert_eq!(Direction::classify(0, 500), Direction::Steady);
+        assert_eq!(Direction::classify(500, 500), Direction::Steady);
+        assert_eq!(Direction::classify(-500, 500), Direction::Steady);
+        assert_eq!(Direction::classify(501, 500), Direction::Rising);
+        assert_eq!(Direction::classify(-501, 500), Direction::Falling);
+    }
+
+    /// A window is opened in winter: Temperature and humidity drop.
+    #[test]
+    fn open_window() {
+        let mut tracker = TrendTracker::<4>::new(CONFIG);
+        assert_eq!(tracker.update(m(21_000, 45_000), 0), None);
+        let trend = tracker.update(m(21_100, 45_500), 30_000).unwrap();
+        assert_eq!(trend.temperature, Direction::Steady);
+        assert_eq!(trend.humidity, Direction::Steady);
+
+        tracker.update(m(20_000, 40_000), 60_000);
+        tracker.update(m(19_000, 35_000), 90_000);
+        let trend = tracker.update(m(18_000, 30_000), 120_000).unwrap();
+        assert_eq!(trend.rate.temperature_per_minute, -2_060);
+        assert_eq!(trend.rate.humidity_per_minute, -10_300);
+        assert_eq!(trend.temperature, Direction::Falling);
+        assert_eq!(trend.humidity, Direction::Falling);
+        assert_eq!(tracker.history().len(), 4);
+    }
+
+    #[test]
+    fn rising() {
+        let mut tracker = TrendTracker::<8>::new(CONFIG);
+        for minute in 0..8 {
+            tracker.update(m(20_000 + minute * 600, 50_000), minute as u64 * 60_000);
+        }
+        let trend = tracker.trend().unwrap();
+        assert_eq!(trend.rate.temperature_per_minute, 600);
+        assert_eq!(trend.temperature, Direction::Rising);
+        assert_eq!(trend.humidity, Direction::Steady);
+
+        // Less sensitive temperature threshold
+        tracker.set_config(TrendConfig {
+            temperature_per_minute: 1_000,
+            humidity_per_minute: 0,
+        });
+        assert_eq!(tracker.trend().unwrap().temperature, Direction::Steady);
+
+        tracker.reset();
+        assert_eq!(tracker.trend(), None);
+    }
+}
@@ -27,6 +27,70 @@ pub struct RawMeasurement {
     pub humidity: u16,
 }
 
+/// Limit checks on raw values.
+///
+/// The thresholds can be computed at compile time, so that limit checks
+/// don't need any conversion math (e.g. in interrupt handlers):
+///
+/// ```
+/// use shtcx::RawMeasurement;
+///
+/// const MAX_TEMPERATURE: u16 = RawMeasurement::temperature_threshold(30_000).unwrap();
+/// const MIN_HUMIDITY: u16 = RawMeasurement::humidity_threshold(20_000).unwrap();
+///
+/// let raw = RawMeasurement { temperature: 0x648b, humidity: 0xa133 };
+/// assert!(raw.temperature_below(MAX_TEMPERATURE));
+/// assert!(raw.humidity_at_least(MIN_HUMIDITY));
+/// ```
+impl RawMeasurement {
+    /// Return the raw temperature threshold for a temperature in
+    /// milli-degrees celsius.
+    ///
+    /// This is the smallest raw value that converts to at least
+    /// `millidegrees` (see [`Temperature::from_raw`]). Return `None` if no
+    /// raw value reaches `millidegrees` (above 129.997 °C).
+    pub const fn temperature_threshold(millidegrees: i32) -> Option<u16> {
+        raw_threshold(millidegrees as i64 + 45000, 21875)
+    }
+
+    /// Return the raw humidity threshold for a humidity in 1/1000 %RH.
+    ///
+    /// This is the smallest raw value that converts to at least
+    /// `millipercent` (see [`Humidity::from_raw`]). Return `None` if no raw
+    /// value reaches `millipercent` (above 99.998 %RH).
+    pub const fn humidity_threshold(millipercent: i32) -> Option<u16> {
+        raw_threshold(millipercent as i64, 12500)
+    }
+
+    /// Return whether the temperature is at or above a threshold from
+    /// [`temperature_threshold`](Self::temperature_threshold).
+    #[inline]
+    pub const fn temperature_at_least(&self, threshold: u16) -> bool {
+        self.temperature >= threshold
+    }
+
+    /// Return whether the temperature is below a threshold from
+    /// [`temperature_threshold`](Self::temperature_threshold).
+    #[inline]
+    pub const fn temperature_below(&self, threshold: u16) -> bool {
+        self.temperature < threshold
+    }
+
+    /// Return whether the humidity is at or above a threshold from
+    /// [`humidity_threshold`](Self::humidity_threshold).
+    #[inline]
+    pub const fn humidity_at_least(&self, threshold: u16) -> bool {
+        self.humidity >= threshold
+    }
+
+    /// Return whether the humidity is below a threshold from
+    /// [`humidity_threshold`](Self::humidity_threshold).
+    #[inline]
+    pub const fn humidity_below(&self, threshold: u16) -> bool {
+        self.humidity < threshold
+    }
+}
+
 impl From<RawMeasurement> for Measurement {
     fn from(other: RawMeasurement) -> Self {
         Self {
@@ -36,12 +100,83 @@ impl From<RawMeasurement> for Measurement {
     }
 }
 
+impl Measurement {
+    /// Calculate the dew point.
+    ///
+    /// Uses the Magnus formula with the parameters for water from the
+    /// Sensirion application note "Introduction to Humidity" (valid for
+    /// temperatures between -45 °C and 60 °C). The humidity is clamped to
+    /// 0.001..100 %RH.
+    pub fn dew_point(&self) -> Temperature {
+        let rh = self.humidity.0.clamp(1, 100_000) as f32 / 100_000.0;
+        let gamma = libm::logf(rh) + magnus_exponent(self.temperature.as_degrees_celsius());
+        let dew_point = MAGNUS_C * gamma / (MAGNUS_B - gamma);
+        Temperature(libm::roundf(dew_point * 1000.0) as i32)
+    }
+}
+
 impl Temperature {
     /// Create a new `Temperature` from a raw measurement result.
+    ///
+    /// The result is truncated to milli-degrees, see
+    /// [`from_raw_rounded`](Self::from_raw_rounded).
     pub fn from_raw(raw: u16) -> Self {
         Self(convert_temperature(raw))
     }
 
+    /// Create a new `Temperature` from a raw measurement result, rounded to
+    /// the nearest milli-degree.
+    ///
+    /// Unlike [`from_raw`](Self::from_raw), this doesn't bias the result
+    /// towards lower temperatures.
+    pub fn from_raw_rounded(raw: u16) -> Self {
+        Self(scale_raw(raw, 175_000) - 45_000)
+    }
+
+    /// Convert a raw measurement result to centi-degrees celsius, rounded to
+    /// the nearest centi-degree.
+    pub fn raw_to_centidegrees_celsius(raw: u16) -> i32 {
+        scale_raw(raw, 17_500) - 4_500
+    }
+
+    /// Convert a raw measurement result to micro-degrees celsius, rounded to
+    /// the nearest micro-degree.
+    ///
+    /// One raw step corresponds to about 2.67 m°C, so this keeps the full
+    /// resolution of the raw value.
+    pub fn raw_to_microdegrees_celsius(raw: u16) -> i32 {
+        scale_raw(raw, 175_000_000) - 45_000_000
+    }
+
+    /// Convert a raw measurement result to degrees celsius.
+    ///
+    /// The result is the datasheet formula rounded to the nearest `f32`.
+    pub fn raw_to_degrees_celsius(raw: u16) -> f32 {
+        // `raw * 175` and the division by 2^16 are exact, only the
+        // subtraction rounds.
+        f32::from(raw) * 175.0 / 65536.0 - 45.0
+    }
+
+    /// Convert a raw measurement result to degrees celsius.
+    ///
+    /// The result is exactly the datasheet formula.
+    pub fn raw_to_degrees_celsius_f64(raw: u16) -> f64 {
+        f64::from(raw) * 175.0 / 65536.0 - 45.0
+    }
+
+    /// Create a new `Temperature` from the mean of `count` raw measurement
+    /// results, given their sum.
+    ///
+    /// Converting the sum instead of the rounded mean avoids rounding loss.
+    pub(crate) fn from_raw_mean(sum: u32, count: u32) -> Self {
+        Self(convert_temperature_mean(sum, count))
+    }
+
+    /// Create a new `Temperature` from milli-degrees celsius.
+    pub const fn from_millidegrees_celsius(millidegrees: i32) -> Self {
+        Self(millidegrees)
+    }
+
     /// Return temperature in milli-degrees celsius.
     pub fn as_millidegrees_celsius(&self) -> i32 {
         self.0
@@ -55,10 +190,65 @@ impl Temperature {
 
 impl Humidity {
     /// Create a new `Humidity` from a raw measurement result.
+    ///
+    /// The result is truncated to 1/1000 %RH, see
+    /// [`from_raw_rounded`](Self::from_raw_rounded).
     pub fn from_raw(raw: u16) -> Self {
         Self(convert_humidity(raw))
     }
 
+    /// Create a new `Humidity` from a raw measurement result, rounded to the
+    /// nearest 1/1000 %RH.
+    ///
+    /// Unlike [`from_raw`](Self::from_raw), this doesn't bias the result
+    /// towards lower humidities.
+    pub fn from_raw_rounded(raw: u16) -> Self {
+        Self(scale_raw(raw, 100_000))
+    }
+
+    /// Convert a raw measurement result to 1/100 %RH, rounded to the nearest
+    /// 1/100 %RH.
+    pub fn raw_to_centipercent(raw: u16) -> i32 {
+        scale_raw(raw, 10_000)
+    }
+
+    /// Convert a raw measurement result to 1/1000000 %RH, rounded to the
+    /// nearest 1/1000000 %RH.
+    ///
+    /// One raw step corresponds to about 1.53 m%RH, so this keeps the full
+    /// resolution of the raw value.
+    pub fn raw_to_micropercent(raw: u16) -> i32 {
+        scale_raw(raw, 100_000_000)
+    }
+
+    /// Convert a raw measurement result to %RH.
+    ///
+    /// The result is exactly the datasheet formula.
+    pub fn raw_to_percent(raw: u16) -> f32 {
+        // `raw * 100` and the division by 2^16 are exact.
+        f32::from(raw) * 100.0 / 65536.0
+    }
+
+    /// Convert a raw measurement result to %RH.
+    ///
+    /// The result is exactly the datasheet formula.
+    pub fn raw_to_percent_f64(raw: u16) -> f64 {
+        f64::from(raw) * 100.0 / 65536.0
+    }
+
+    /// Create a new `Humidity` from the mean of `count` raw measurement
+    /// results, given their sum.
+    ///
+    /// Converting the sum instead of the rounded mean avoids rounding loss.
+    pub(crate) fn from_raw_mean(sum: u32, count: u32) -> Self {
+        Self(convert_humidity_mean(sum, count))
+    }
+
+    /// Create a new `Humidity` from 1/1000 %RH.
+    pub const fn from_millipercent(millipercent: i32) -> Self {
+        Self(millipercent)
+    }
+
     /// Return relative humidity in 1/1000 %RH.
     pub fn as_millipercent(&self) -> i32 {
         self.0
@@ -70,6 +260,19 @@ impl Humidity {
     }
 }
 
+/// Magnus coefficient B for water (Sensirion application note "Introduction
+/// to Humidity").
+const MAGNUS_B: f32 = 17.62;
+/// Magnus coefficient C for water in °C.
+const MAGNUS_C: f32 = 243.12;
+
+/// Return the exponent of the Magnus formula for the saturation vapor
+/// pressure at `t` °C: `ln(E(t) / 6.112 hPa)`.
+#[inline]
+pub(crate) fn magnus_exponent(t: f32) -> f32 {
+    MAGNUS_B * t / (MAGNUS_C + t)
+}
+
 /// Convert raw temperature measurement to milli-degrees celsius.
 ///
 /// Formula (datasheet 5.11): -45 + 175 * (val / 2^16),
@@ -88,6 +291,40 @@ fn convert_humidity(humi_raw: u16) -> i32 {
     (((humi_raw as u32) * 12500) >> 13) as i32
 }
 
+/// Return `raw * full_scale / 2^16`, rounded to nearest (ties up).
+#[inline]
+const fn scale_raw(raw: u16, full_scale: u32) -> i32 {
+    ((raw as u64 * full_scale as u64 + (1 << 15)) >> 16) as i32
+}
+
+/// Return the smallest raw value `raw` with `(raw * factor) >> 13 >= value`,
+/// the inverse of the fixed point conversions, or `None` if there is none.
+const fn raw_threshold(value: i64, factor: i64) -> Option<u16> {
+    if value <= 0 {
+        return Some(0);
+    }
+    let raw = ((value << 13) + factor - 1) / factor;
+    if raw > u16::MAX as i64 {
+        None
+    } else {
+        Some(raw as u16)
+    }
+}
+
+/// Convert the sum of `count` raw temperature measurements to their mean in
+/// milli-degrees celsius.
+#[inline]
+fn convert_temperature_mean(sum: u32, count: u32) -> i32 {
+    ((u64::from(sum) * 21875) / (u64::from(count.max(1)) << 13)) as i32 - 45000
+}
+
+/// Convert the sum of `count` raw humidity measurements to their mean
+/// relative humidity.
+#[inline]
+fn convert_humidity_mean(sum: u32, count: u32) -> i32 {
+    ((u64::from(sum) * 12500) / (u64::from(count.max(1)) << 13)) as i32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -128,9 +365,175 @@ mod tests {
         assert_eq!(humidity, 62968);
     }
 
+    /// The mean of raw values is converted without rounding the mean first.
+    #[test]
+    fn test_convert_mean() {
+        let raw = (0b0110_0100_u16 << 8) | 0b1000_1011;
+        assert_eq!(convert_temperature_mean(u32::from(raw), 1), 23730);
+        assert_eq!(convert_humidity_mean(0xa133, 1), 62968);
+        // Mean of raw + 0 and raw + 1 is raw + 0.5
+        assert_eq!(convert_temperature_mean(2 * u32::from(raw) + 1, 2), 23731);
+        assert_eq!(convert_temperature_mean(0, 0), -45000);
+    }
+
+    /// Rounded conversions of all raw values are within half a unit of the
+    /// datasheet formula.
+    #[test]
+    fn rounded_conversions_exhaustive() {
+        // Datasheet formula times 2^16 in the unit given by `scale`
+        let temperature = |raw: u16, scale: i64| 175 * scale * i64::from(raw) - 45 * scale * 65536;
+        let humidity = |raw: u16, scale: i64| 100 * scale * i64::from(raw);
+        let assert_rounded = |value: i32, exact: i64| {
+            let error = (i64::from(value) << 16) - exact;
+            assert!((-32768..=32768).contains(&error), "{value}, {exact}");
+        };
+        for raw in 0..=u16::MAX {
+            assert_rounded(
+                Temperature::from_raw_rounded(raw).as_millidegrees_celsius(),
+                temperature(raw, 1_000),
+            );
+            assert_rounded(
+                Temperature::raw_to_centidegrees_celsius(raw),
+                temperature(raw, 100),
+            );
+            assert_rounded(
+                Temperature::raw_to_microdegrees_celsius(raw),
+                temperature(raw, 1_000_000),
+            );
+            assert_rounded(
+                Humidity::from_raw_rounded(raw).as_millipercent(),
+                humidity(raw, 1_000),
+            );
+            assert_rounded(Humidity::raw_to_centipercent(raw), humidity(raw, 100));
+            assert_rounded(Humidity::raw_to_micropercent(raw), humidity(raw, 1_000_000));
+
+            // The truncating conversions are at most one unit below
+            let error = (i64::from(convert_temperature(raw)) << 16) - temperature(raw, 1_000);
+            assert!((-65536..=0).contains(&error));
+            let error = (i64::from(convert_humidity(raw)) << 16) - humidity(raw, 1_000);
+            assert!((-65536..=0).contains(&error));
+        }
+    }
+
+    /// Floating point conversions of all raw values are exact (`f64`) or
+    /// correctly rounded (`f32`).
+    #[test]
+    fn float_conversions_exhaustive() {
+        for raw in 0..=u16::MAX {
+            let temperature = Temperature::raw_to_degrees_celsius_f64(raw);
+            assert_eq!(
+                temperature * 65536.0,
+                (175 * i64::from(raw) - 45 * 65536) as f64
+            );
+            assert_eq!(Temperature::raw_to_degrees_celsius(raw), temperature as f32);
+            let humidity = Humidity::raw_to_percent_f64(raw);
+            assert_eq!(humidity * 65536.0, (100 * i64::from(raw)) as f64);
+            assert_eq!(Humidity::raw_to_percent(raw), humidity as f32);
+        }
+    }
+
+    #[test]
+    fn rounded_conversions() {
+        // Datasheet setion 5.11 "Conversion of Sensor Output": 23.7305 °C,
+        // 62.9684 %RH
+        assert_eq!(
+            Temperature::from_raw_rounded(0x648b).as_millidegrees_celsius(),
+            23731
+        );
+        assert_eq!(Temperature::raw_to_centidegrees_celsius(0x648b), 2373);
+        assert_eq!(Temperature::raw_to_microdegrees_celsius(0x648b), 23_730_545);
+        assert_eq!(Humidity::from_raw_rounded(0xa133).as_millipercent(), 62968);
+        assert_eq!(Humidity::raw_to_centipercent(0xa133), 6297);
+        assert_eq!(Humidity::raw_to_micropercent(0xa133), 62_968_445);
+        assert_eq!(
+            Temperature::raw_to_microdegrees_celsius(u16::MAX),
+            129_997_330
+        );
+        assert_eq!(Humidity::raw_to_micropercent(u16::MAX), 99_998_474);
+    }
+
+    /// A threshold is the smallest raw value converting to at least the
+    /// given value.
+    #[test]
+    fn raw_thresholds() {
+        for millidegrees in -50_000..=135_000 {
+            match RawMeasurement::temperature_threshold(millidegrees) {
+                Some(threshold) => {
+                    assert!(convert_temperature(threshold) >= millidegrees);
+                    if threshold > 0 {
+                        assert!(convert_temperature(threshold - 1) < millidegrees);
+                    }
+                }
+                // Not even the maximum raw value reaches the limit
+                None => assert!(convert_temperature(u16::MAX) < millidegrees),
+            }
+        }
+        for millipercent in -1_000..=101_000 {
+            match RawMeasurement::humidity_threshold(millipercent) {
+                Some(threshold) => {
+                    assert!(convert_humidity(threshold) >= millipercent);
+                    if threshold > 0 {
+                        assert!(convert_humidity(threshold - 1) < millipercent);
+                    }
+                }
+                None => assert!(convert_humidity(u16::MAX) < millipercent),
+            }
+        }
+        assert_eq!(RawMeasurement::temperature_threshold(i32::MIN), Some(0));
+        assert_eq!(
+            RawMeasurement::temperature_threshold(129_997),
+            Some(u16::MAX)
+        );
+        assert_eq!(RawMeasurement::temperature_threshold(129_998), None);
+        assert_eq!(RawMeasurement::temperature_threshold(i32::MAX), None);
+        assert_eq!(RawMeasurement::humidity_threshold(99_998), Some(u16::MAX));
+        assert_eq!(RawMeasurement::humidity_threshold(99_999), None);
+        assert_eq!(RawMeasurement::humidity_threshold(i32::MAX), None);
+    }
+
+    #[test]
+    fn raw_comparisons() {
+        // Datasheet setion 5.11 "Conversion of Sensor Output"
+        let raw = RawMeasurement {
+            temperature: 0x648b,
+            humidity: 0xa133,
+        };
+        let measurement = Measurement::from(raw);
+        for delta in [-1, 0, 1] {
+            let temperature = measurement.temperature.0 + delta;
+            let threshold = RawMeasurement::temperature_threshold(temperature).unwrap();
+            assert_eq!(raw.temperature_at_least(threshold), delta <= 0);
+            assert_eq!(raw.temperature_below(threshold), delta > 0);
+            let humidity = measurement.humidity.0 + delta;
+            let threshold = RawMeasurement::humidity_threshold(humidity).unwrap();
+            assert_eq!(raw.humidity_at_least(threshold), delta <= 0);
+            assert_eq!(raw.humidity_below(threshold), delta > 0);
+        }
+    }
+
+    #[test]
+    fn dew_point() {
+        let dew_point = |t, rh| {
+            Measurement {
+                temperature: Temperature(t),
+                humidity: Humidity(rh),
+            }
+            .dew_point()
+            .as_millidegrees_celsius()
+        };
+        // At 100 %RH, the dew point is the temperature
+        assert_eq!(dew_point(20_000, 100_000), 20_000);
+        assert!((dew_point(25_000, 50_000) - 13_850).abs() < 10);
+        assert!((dew_point(-10_000, 80_000) - -12_797).abs() < 10);
+        // Humidity is clamped
+        assert_eq!(dew_point(20_000, 120_000), 20_000);
+        assert!(dew_point(20_000, 0) < -80_000);
+    }
+
     #[test]
     fn temperature() {
         let temp = Temperature(24123);
+        assert_eq!(temp, Temperature::from_millidegrees_celsius(24123));
         assert_eq!(temp.as_millidegrees_celsius(), 24123);
         assert_eq!(temp.as_degrees_celsius(), 24.123);
     }
@@ -138,6 +541,7 @@ mod tests {
     #[test]
     fn humidity() {
         let humi = Humidity(65432);
+        assert_eq!(humi, Humidity::from_millipercent(65432));
         assert_eq!(humi.as_millipercent(), 65432);
         assert_eq!(humi.as_percent(), 65.432);
     }
@@ -0,0 +1,279 @@
+//! Plausibility checks for measurements.
+//!
+//! A failing sensor may return measurements with valid CRCs that are still
+//! garbage. The [`Validator`] checks every measurement and returns a
+//! [`Verdict`] instead of silently passing the data through. It detects:
+//!
+//! - Saturated raw values (`0x0000` or `0xFFFF`)
+//! - Values outside of the specified range of the sensor (-40..125 °C,
+//!   0..100 %RH)
+//! - Stuck readings: The same measurement is returned over and over again
+//! - Implausible step changes between consecutive measurements
+//!
+//! ```
+//! use shtcx::validate::{Validator, ValidatorConfig};
+//! use shtcx::RawMeasurement;
+//!
+//! let mut validator = Validator::new(ValidatorConfig {
+//!     stuck_samples: 100,
+//!     max_temperature_step: Some(5_000),
+//!     max_humidity_step: Some(20_000),
+//! });
+//! let raw = RawMeasurement { temperature: 0xffff, humidity: 0x8000 };
+//! let verdict = validator.check_raw(raw);
+//! assert!(verdict.saturated);
+//! assert!(!verdict.is_valid());
+//! ```
+
+use crate::{Measurement, RawMeasurement};
+
+/// Lower limit of the specified temperature range in m°C.
+const MIN_TEMPERATURE: i32 = -40_000;
+/// Upper limit of the specified temperature range in m°C.
+const MAX_TEMPERATURE: i32 = 125_000;
+/// Upper limit of the humidity range in 1/1000 %RH.
+const MAX_HUMIDITY: i32 = 100_000;
+
+/// Configuration of a [`Validator`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ValidatorConfig {
+    /// Number of identical consecutive measurements after which the sensor
+    /// is considered stuck (0 disables the check).
+    pub stuck_samples: u16,
+    /// Maximum plausible temperature change between two consecutive
+    /// measurements in m°C.
+    pub max_temperature_step: Option<u32>,
+    /// Maximum plausible humidity change between two consecutive
+    /// measurements in 1/1000 %RH.
+    pub max_humidity_step: Option<u32>,
+}
+
+/// Result of a plausibility check.
+///
+/// Each field is `true` if the corresponding check failed.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Verdict {
+    /// A raw value is `0x0000` or `0xFFFF`.
+    pub saturated: bool,
+    /// A value is outside of the specified range of the sensor.
+    pub out_of_range: bool,
+    /// The measurement was identical for the configured number of samples.
+    pub stuck: bool,
+    /// The change since the previous measurement is implausibly large.
+    pub step: bool,
+}
+
+impl Verdict {
+    /// Return whether all checks passed.
+    pub fn is_valid(&self) -> bool {
+        *self == Verdict::default()
+    }
+}
+
+/// Plausibility checker for a series of measurements.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    config: ValidatorConfig,
+    /// Previous measurement.
+    previous: Option<Measurement>,
+    /// Number of consecutive identical measurements.
+    repeated: u16,
+    /// Previous measurement that was neither saturated nor out of range.
+    reference: Option<Measurement>,
+}
+
+impl Validator {
+    /// Create a new validator.
+    pub fn new(config: ValidatorConfig) -> Self {
+        Self {
+            config,
+            previous: None,
+            repeated: 0,
+            reference: None,
+        }
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &ValidatorConfig {
+        &self.config
+    }
+
+    /// Forget all previous measurements.
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.repeated = 0;
+        self.reference = None;
+    }
+
+    /// Check a raw measurement.
+    ///
+    /// In addition to the checks of [`check`](Validator::check), saturated
+    /// raw values are detected.
+    pub fn check_raw(&mut self, raw: RawMeasurement) -> Verdict {
+        let saturated = |value| value == 0x0000 || value == 0xffff;
+        self.evaluate(
+            &raw.into(),
+            saturated(raw.temperature) || saturated(raw.humidity),
+        )
+    }
+
+    /// Check a measurement.
+    ///
+    /// Step changes are checked against the previous measurement that was
+    /// neither saturated nor out of range, so after a spike, the next
+    /// measurement is flagged as well.
+    pub fn check(&mut self, measurement: &Measurement) -> Verdict {
+        self.evaluate(measurement, false)
+    }
+
+    fn evaluate(&mut self, measurement: &Measurement, saturated: bool) -> Verdict {
+        let temperature = measurement.temperature.as_millidegrees_celsius();
+        let humidity = measurement.humidity.as_millipercent();
+        let out_of_range = !(MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&temperature)
+            || !(0..=MAX_HUMIDITY).contains(&humidity);
+
+        if self.previous == Some(*measurement) {
+            self.repeated = self.repeated.saturating_add(1);
+        } else {
+            self.repeated = 1;
+        }
+        self.previous = Some(*measurement);
+        let stuck = self.config.stuck_samples > 0 && self.repeated >= self.config.stuck_samples;
+
+        let step = self.reference.is_some_and(|reference| {
+            let exceeds = |max: Option<u32>, previous: i32, current: i32| {
+                max.is_some_and(|max| previous.abs_diff(current) > max)
+            };
+            exceeds(
+                self.config.max_temperature_step,
+                reference.temperature.as_millidegrees_celsius(),
+                temperature,
+            ) || exceeds(
+                self.config.max_humidity_step,
+                reference.humidity.as_millipercent(),
+                humidity,
+            )
+        });
+        if !saturated && !out_of_range {
+            self.reference = Some(*measurement);
+        }
+
+        Verdict {
+            saturated,
+            out_of_range,
+            stuck,
+            step,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Humidity, Temperature};
+
+    const CONFIG: ValidatorConfig = ValidatorConfig {
+        stuck_samples: 3,
+        max_temperature_step: Some(5_000),
+        max_humidity_step: Some(20_000),
+    };
+
+    fn m(temperature: i32, humidity: i32) -> Measurement {
+        Measurement {
+            temperature: Temperature::from_millidegrees_celsius(temperature),
+            humidity: Humidity::from_millipercent(humidity),
+        }
+    }
+
+    #[test]
+    fn valid() {
+        let mut validator = Validator::new(CONFIG);
+        assert!(validator.check(&m(20_000, 40_000)).is_valid());
+        assert!(validator.check(&m(21_000, 45_000)).is_valid());
+        validator.reset();
+        assert!(validator.check(&m(-40_000, 0)).is_valid());
+        validator.reset();
+        assert!(validator.check(&m(125_000, 100_000)).is_valid());
+    }
+
+    #[test]
+    fn saturated() {
+        let mut validator = Validator::new(CONFIG);
+        let verdict = validator.check_raw(RawMeasurement {
+            temperature: 0x6666,
+            humidity: 0x0000,
+        });
+        assert_eq!(
+            verdict,
+            Verdict {
+                saturated: true,
+                ..Verdict::default()
+            }
+        );
+        let verdict = validator.check_raw(RawMeasurement {
+            temperature: 0xffff,
+            humidity: 0x8000,
+        });
+        assert!(verdict.saturated);
+        // 130 °C
+        assert!(verdict.out_of_range);
+        assert!(validator
+            .check_raw(RawMeasurement {
+                temperature: 0x6666,
+                humidity: 0x8000,
+            })
+            .is_valid());
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut validator = Validator::new(CONFIG);
+        assert!(validator.check(&m(-40_001, 50_000)).out_of_range);
+        assert!(validator.check(&m(125_001, 50_000)).out_of_range);
+        assert!(validator.check(&m(20_000, -1)).out_of_range);
+        assert!(validator.check(&m(20_000, 100_001)).out_of_range);
+    }
+
+    #[test]
+    fn stuck() {
+        let mut validator = Validator::new(CONFIG);
+        assert!(!validator.check(&m(20_000, 40_000)).stuck);
+        assert!(!validator.check(&m(20_000, 40_000)).stuck);
+        assert!(validator.check(&m(20_000, 40_000)).stuck);
+        assert!(validator.check(&m(20_000, 40_000)).stuck);
+        assert!(!validator.check(&m(20_000, 40_001)).stuck);
+
+        // Disabled
+        let mut validator = Validator::new(ValidatorConfig {
+            stuck_samples: 0,
+            ..CONFIG
+        });
+        for _ in 0..10 {
+            assert!(validator.check(&m(20_000, 40_000)).is_valid());
+        }
+    }
+
+    #[test]
+    fn step() {
+        let mut validator = Validator::new(CONFIG);
+        assert!(validator.check(&m(20_000, 40_000)).is_valid());
+        assert!(validator.check(&m(25_000, 60_000)).is_valid());
+        // Spike
+        assert!(validator.check(&m(30_001, 60_000)).step);
+        assert!(validator.check(&m(25_000, 60_000)).step);
+        assert!(validator.check(&m(25_000, 39_999)).step);
+        // Out of range measurements are not used as reference
+        assert!(validator.check(&m(200_000, 40_000)).out_of_range);
+        assert!(validator.check(&m(25_000, 40_000)).is_valid());
+
+        // Disabled
+        let mut validator = Validator::new(ValidatorConfig {
+            max_temperature_step: None,
+            max_humidity_step: None,
+            ..CONFIG
+        });
+        validator.check(&m(-40_000, 0));
+        assert!(validator.check(&m(125_000, 100_000)).is_valid());
+    }
+}